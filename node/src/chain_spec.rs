@@ -169,8 +169,16 @@ fn testnet_genesis(
 					utxo::TransactionOutput {
 						value: 100 as utxo::Value,
 						pub_key: H256::from_slice(x.as_slice()),
+						lock_height: 0,
+						asset_id: utxo::NATIVE_ASSET,
+						metadata: Vec::new(),
+						memo: Vec::new(),
+						requires_gov_flag: None,
 					} )
-				.collect()
+				.collect(),
+			asset_supplies: Default::default(),
+			initial_reward: 0,
+			total_genesis_supply: 100 * endowed_utxos.len() as utxo::Value,
 		})
 	}
 }
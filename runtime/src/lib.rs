@@ -99,10 +99,10 @@ pub const VERSION: RuntimeVersion = RuntimeVersion {
 	spec_name: create_runtime_str!("node-utxo"),
 	impl_name: create_runtime_str!("node-utxo"),
 	authoring_version: 1,
-	spec_version: 1,
+	spec_version: 2,
 	impl_version: 1,
 	apis: RUNTIME_API_VERSIONS,
-	transaction_version: 1,
+	transaction_version: 2,
 };
 
 pub const MILLISECS_PER_BLOCK: u64 = 6000;
@@ -270,8 +270,96 @@ impl pallet_template::Trait for Runtime {
 	type Event = Event;
 }
 
+parameter_types! {
+	pub const MaxRewardRemainder: utxo::Value = 1_000;
+	pub const MinOutputs: u32 = 1;
+	pub const RewardMergeWindow: u32 = 0;
+	pub const DustThreshold: utxo::Value = 10;
+	pub const MaxTransactionsPerBlock: u32 = 1_000;
+	pub const RewardDecay: Permill = Permill::from_percent(0);
+	pub const MaxMetadataLength: u32 = 64;
+	pub const StrictBalance: bool = false;
+	pub const RewardHistoryRetention: u32 = 7 * DAYS;
+	pub const CoinbaseMaturity: u32 = 100;
+	pub const FeeRateWindow: u32 = 20;
+	pub const MaxBalancePerKey: utxo::Value = utxo::Value::max_value();
+	pub const MaxUtxosPerOwner: u32 = 1_000;
+	pub const MinimumFee: utxo::Value = 10;
+	pub const ConsolidationDiscount: Permill = Permill::from_percent(50);
+	pub const MaxMemoLength: u32 = 128;
+	pub const MinInputValue: utxo::Value = 10;
+	pub const MaxFee: utxo::Value = 1_000_000;
+	pub const RewardEpochLength: u32 = HOURS;
+	pub const UnbondingPeriod: u32 = 7 * DAYS;
+	pub const RewardTotalHighWater: utxo::Value = utxo::Value::max_value() / 10 * 9;
+	pub const MaxSweepPerBlock: u32 = 256;
+	pub const BatchVerifyThreshold: u32 = 0;
+	pub const RewardDustIdlePeriod: u32 = DAYS;
+	pub const MinRewardUptime: u32 = 0;
+	pub const RewardDenomination: utxo::Value = 0;
+	pub const MaxAssetsPerTransaction: u32 = 8;
+	pub const RewardReorgWindow: u32 = 0;
+	pub const FeePerNewOutput: utxo::Value = 2;
+	pub const RewardVestingPeriod: u32 = 0;
+	pub const RewardVestingTranches: u32 = 4;
+	pub const RewardCliffBlocks: u32 = 0;
+	pub const ConsolidateChange: bool = false;
+	pub const EmitStateDelta: bool = false;
+	pub const RollbackWindow: u32 = HOURS;
+}
+
+/// Swept reward dust goes to the sudo key, standing in for a treasury.
+pub struct RewardDustKey;
+
+impl frame_support::traits::Get<Hash> for RewardDustKey {
+	fn get() -> Hash {
+		let key: [u8; 32] = Sudo::key().into();
+		key.into()
+	}
+}
+
 impl utxo::Trait for Runtime {
 	type Event = Event;
+	type MaxRewardRemainder = MaxRewardRemainder;
+	type MinOutputs = MinOutputs;
+	type RewardMergeWindow = RewardMergeWindow;
+	type DustThreshold = DustThreshold;
+	type MaxTransactionsPerBlock = MaxTransactionsPerBlock;
+	type RewardDecay = RewardDecay;
+	type MaxMetadataLength = MaxMetadataLength;
+	type StrictBalance = StrictBalance;
+	type RewardHistoryRetention = RewardHistoryRetention;
+	type CoinbaseMaturity = CoinbaseMaturity;
+	type FeeRateWindow = FeeRateWindow;
+	type MaxBalancePerKey = MaxBalancePerKey;
+	type MaxUtxosPerOwner = MaxUtxosPerOwner;
+	type MinimumFee = MinimumFee;
+	type ConsolidationDiscount = ConsolidationDiscount;
+	type PreFilter = ();
+	type PostSpend = ();
+	type MaxMemoLength = MaxMemoLength;
+	type WeightInfo = ();
+	type MinInputValue = MinInputValue;
+	type MaxFee = MaxFee;
+	type RewardEpochLength = RewardEpochLength;
+	type UnbondingPeriod = UnbondingPeriod;
+	type RewardTotalHighWater = RewardTotalHighWater;
+	type MaxSweepPerBlock = MaxSweepPerBlock;
+	type BatchVerifyThreshold = BatchVerifyThreshold;
+	type RewardDustIdlePeriod = RewardDustIdlePeriod;
+	type RewardDustKey = RewardDustKey;
+	type MinRewardUptime = MinRewardUptime;
+	type RewardDenomination = RewardDenomination;
+	type MaxAssetsPerTransaction = MaxAssetsPerTransaction;
+	type ForceOrigin = frame_system::EnsureRoot<AccountId>;
+	type RewardReorgWindow = RewardReorgWindow;
+	type FeePerNewOutput = FeePerNewOutput;
+	type RewardVestingPeriod = RewardVestingPeriod;
+	type RewardVestingTranches = RewardVestingTranches;
+	type RewardCliffBlocks = RewardCliffBlocks;
+	type ConsolidateChange = ConsolidateChange;
+	type EmitStateDelta = EmitStateDelta;
+	type RollbackWindow = RollbackWindow;
 }
 
 // Create the runtime by composing the FRAME pallets that were previously configured.
@@ -461,6 +549,71 @@ impl_runtime_apis! {
 		}
 	}
 
+	impl utxo::UtxoApi<Block> for Runtime {
+		fn fee_for_priority(target_priority: u64, tx_size: u32) -> utxo::Value {
+			Utxo::fee_for_priority(target_priority, tx_size)
+		}
+
+		fn creator_of(out_point: Hash) -> Option<Hash> {
+			Utxo::creator_of(out_point)
+		}
+
+		fn get_utxo(out_point: Hash) -> Option<utxo::TransactionOutput> {
+			Utxo::get_utxo(out_point)
+		}
+
+		fn fee_rate(transaction: utxo::Transaction) -> Result<u64, Vec<u8>> {
+			Utxo::fee_rate(&transaction).map_err(|e| e.as_bytes().to_vec())
+		}
+
+		fn rewards_in_block(block: u64) -> Vec<(Hash, utxo::Value)> {
+			Utxo::rewards_in_block(block)
+		}
+
+		fn is_coinbase(out_point: Hash) -> bool {
+			Utxo::is_coinbase(&out_point)
+		}
+
+		fn suggested_fee_rate() -> u64 {
+			Utxo::suggested_fee_rate()
+		}
+
+		fn balance_of(pub_key: Hash) -> utxo::Value {
+			Utxo::balance_of(pub_key)
+		}
+
+		fn utxos_of(pub_key: Hash) -> Vec<(Hash, utxo::TransactionOutput)> {
+			Utxo::utxos_of(pub_key)
+		}
+
+		fn balance_of_at(pub_key: Hash) -> utxo::Value {
+			Utxo::owned_value(pub_key)
+		}
+
+		fn proof_of_reserves(keys: Vec<Hash>) -> utxo::Value {
+			Utxo::proof_of_reserves(&keys)
+		}
+
+		fn attested_reserves(
+			challenge: Vec<u8>,
+			attestations: Vec<(Hash, sp_core::H512)>,
+		) -> Result<utxo::Value, Vec<u8>> {
+			Utxo::attested_reserves(&challenge, &attestations).map_err(|e| e.as_bytes().to_vec())
+		}
+
+		fn fees_collected(from: u64, to: u64) -> Result<utxo::Value, Vec<u8>> {
+			Utxo::fees_collected(from, to).map_err(|e| e.as_bytes().to_vec())
+		}
+
+		fn largest_utxo(pub_key: Hash) -> Option<(Hash, utxo::Value)> {
+			Utxo::largest_utxo(pub_key)
+		}
+
+		fn inputs_available(out_points: Vec<Hash>) -> Vec<bool> {
+			Utxo::inputs_available(&out_points)
+		}
+	}
+
 	#[cfg(feature = "runtime-benchmarks")]
 	impl frame_benchmarking::Benchmark<Block> for Runtime {
 		fn dispatch_benchmark(
@@ -490,6 +643,7 @@ impl_runtime_apis! {
 			add_benchmark!(params, batches, frame_system, SystemBench::<Runtime>);
 			add_benchmark!(params, batches, pallet_balances, Balances);
 			add_benchmark!(params, batches, pallet_timestamp, Timestamp);
+			add_benchmark!(params, batches, utxo, Utxo);
 
 			if batches.is_empty() { return Err("Benchmark not found for this pallet.".into()) }
 			Ok(batches)
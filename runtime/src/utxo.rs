@@ -1,21 +1,365 @@
 use super::Aura;
-use codec::{Decode, Encode};
+use codec::{Compact, CompactLen, Decode, Encode};
 use frame_support::{
     decl_event, decl_module, decl_storage,
     dispatch::{DispatchResult, Vec},
     ensure,
+    storage::{IterableStorageMap, StoragePrefixedMap},
+    traits::{EnsureOrigin, Get},
+    weights::Weight,
 };
+use frame_system::ensure_root;
 use sp_core::{H256, H512};
 use sp_io;
 #[cfg(feature = "std")]
 use serde::{Deserialize, Serialize};
+#[cfg(feature = "std")]
+use sp_core::crypto::{Ss58AddressFormat, Ss58Codec};
+#[cfg(feature = "std")]
+use curve25519_dalek::{
+    constants::RISTRETTO_BASEPOINT_TABLE, ristretto::CompressedRistretto, scalar::Scalar,
+};
 use sp_core::sr25519::{Public, Signature};
+use sp_runtime::{helpers_128bit::multiply_by_rational, Permill};
 use sp_runtime::traits::{BlakeTwo256, Hash, SaturatedConversion};
 use sp_std::collections::btree_map::BTreeMap;
+use sp_std::vec;
 use sp_runtime::transaction_validity::{TransactionLongevity, ValidTransaction};
 
+#[cfg(test)]
+mod mock;
+
+#[cfg(test)]
+mod tests;
+
+#[cfg(feature = "runtime-benchmarks")]
+mod benchmarking;
+
 pub trait Trait: frame_system::Trait {
     type Event: From<Event> + Into<<Self as frame_system::Trait>::Event>;
+
+    /// The largest remainder of splitting a block reward into shares that's carried to the next
+    /// block; anything above it is burned. Value held back by `RewardDecay` or left unpaid for
+    /// lack of UTXO room isn't counted and is carried in full.
+    type MaxRewardRemainder: Get<Value>;
+
+    /// The fewest spendable outputs a transaction may create.
+    type MinOutputs: Get<u32>;
+
+    /// Rewards minted within this many blocks of an authority's previous reward are merged into
+    /// it instead of creating another UTXO. Zero disables merging.
+    type RewardMergeWindow: Get<u32>;
+
+    /// The smallest value a spendable output may carry.
+    type DustThreshold: Get<Value>;
+
+    /// The most `spend` calls accepted in one block.
+    type MaxTransactionsPerBlock: Get<u32>;
+
+    /// The share of `RewardTotal` held back each block, smoothing payouts into an exponential
+    /// moving average of fee income. Zero pays everything out immediately.
+    type RewardDecay: Get<Permill>;
+
+    /// The longest `metadata` an output may carry.
+    type MaxMetadataLength: Get<u32>;
+
+    /// Whether native inputs must equal native outputs exactly, so fees can only be paid with
+    /// outputs to `FEE_ADDRESS`.
+    type StrictBalance: Get<bool>;
+
+    /// How many blocks of per-block reward records are kept.
+    type RewardHistoryRetention: Get<u32>;
+
+    /// How many blocks a reward UTXO must wait before it can be spent.
+    type CoinbaseMaturity: Get<u32>;
+
+    /// How many recent blocks the suggested fee rate is taken over.
+    type FeeRateWindow: Get<u32>;
+
+    /// The most native value a key may hold across its UTXOs. Transactions that would take a
+    /// recipient above it are rejected.
+    type MaxBalancePerKey: Get<Value>;
+
+    /// The most UTXOs tracked for one owner. Calls that would create more are rejected; rewards
+    /// to a full owner are carried to the next block instead.
+    type MaxUtxosPerOwner: Get<u32>;
+
+    /// The smallest fee a transaction may pay.
+    type MinimumFee: Get<Value>;
+
+    /// How much of `MinimumFee` is waived for transactions that create fewer UTXOs than they
+    /// spend, making consolidation cheaper than fan-out.
+    type ConsolidationDiscount: Get<Permill>;
+
+    /// Custom acceptance rules, checked before anything else when validating a transaction.
+    type PreFilter: TransactionFilter;
+
+    /// Notified of every transaction applied by `spend`.
+    type PostSpend: OnTransactionApplied;
+
+    /// The longest encrypted `memo` an output may carry.
+    type MaxMemoLength: Get<u32>;
+
+    /// Weights of this module's calls.
+    type WeightInfo: WeightInfo;
+
+    /// The smallest UTXO that can be spent by a transaction that doesn't shrink the UTXO set.
+    /// Smaller ones have to be consolidated.
+    type MinInputValue: Get<Value>;
+
+    /// The largest implicit fee, native inputs less native outputs, a transaction may leave.
+    /// Guards against forgotten change outputs; outputs to `FEE_ADDRESS` aren't limited.
+    type MaxFee: Get<Value>;
+
+    /// Rewards minted to an authority within one epoch of this many blocks are pooled into a
+    /// single UTXO, each reward replacing the pool with a larger one. Zero disables pooling.
+    type RewardEpochLength: Get<u32>;
+
+    /// How many blocks unbonded UTXOs stay locked before they can be withdrawn.
+    type UnbondingPeriod: Get<u32>;
+
+    /// `RewardTotalHigh` is emitted when `RewardTotal` rises above this.
+    type RewardTotalHighWater: Get<Value>;
+
+    /// The most steps an expiry sweep takes in one block. Refunding an entry or finishing an
+    /// expiry height is one step each; the rest carries over to the next block.
+    type MaxSweepPerBlock: Get<u32>;
+
+    /// Transactions with at least this many inputs have all their signatures checked before one
+    /// error is reported for them, rather than stopping at the first bad one. Zero disables it.
+    type BatchVerifyThreshold: Get<u32>;
+
+    /// How many blocks `RewardTotal` must stay too small to give every authority a share before
+    /// anyone may sweep it to `RewardDustKey` with `sweep_reward_dust`.
+    type RewardDustIdlePeriod: Get<u32>;
+
+    /// The key swept reward dust is minted to.
+    type RewardDustKey: Get<H256>;
+
+    /// How many blocks an authority must have been in the set before it shares in rewards.
+    /// Until then its share goes to the eligible authorities.
+    type MinRewardUptime: Get<u32>;
+
+    /// Reward shares above this are paid as UTXOs of this value plus one for the remainder,
+    /// instead of a single UTXO. They aren't merged with earlier rewards. Zero disables it.
+    type RewardDenomination: Get<Value>;
+
+    /// The most distinct assets, native included, a transaction's inputs and outputs may hold.
+    type MaxAssetsPerTransaction: Get<u32>;
+
+    /// The origin allowed to `force_spend` past its inputs' locks.
+    type ForceOrigin: EnsureOrigin<Self::Origin>;
+
+    /// How many blocks after minting a reward UTXO stays unspendable in case the block is
+    /// reorganised away. It's checked alongside `CoinbaseMaturity`, so rewards wait for the
+    /// longer of the two. Zero disables it.
+    type RewardReorgWindow: Get<u32>;
+
+    /// How much `MinimumFee` rises for each UTXO a transaction adds to the set, net of the ones
+    /// it spends.
+    type FeePerNewOutput: Get<Value>;
+
+    /// Rewards vest over this many blocks after minting, paid as `RewardVestingTranches` UTXOs
+    /// unlocking evenly over the period. They aren't merged or denominated. Zero disables it.
+    type RewardVestingPeriod: Get<u32>;
+
+    /// How many UTXOs a vesting reward is split into.
+    type RewardVestingTranches: Get<u32>;
+
+    /// Vesting rewards release nothing for this many blocks after minting. Tranches due sooner
+    /// unlock at the cliff instead.
+    type RewardCliffBlocks: Get<u32>;
+
+    /// Whether `send` spends one of the sender's small UTXOs into the change it returns, so the
+    /// payment doesn't grow the UTXO set.
+    type ConsolidateChange: Get<bool>;
+
+    /// Whether each block ends with a `BlockStateDelta` event summarising its net changes to the
+    /// UTXO set.
+    type EmitStateDelta: Get<bool>;
+
+    /// For how many blocks a record of what each transaction spent and created is kept, so
+    /// `rollback_transaction` can undo it. Zero keeps no records.
+    type RollbackWindow: Get<u32>;
+}
+
+pub trait WeightInfo {
+    /// The weight of `spend` for a transaction with `inputs` inputs, `outputs` outputs and an
+    /// encoded size of `bytes`. The size part covers storage and proof size.
+    fn spend(inputs: u32, outputs: u32, bytes: u32) -> Weight;
+}
+
+/// Weights shaped after the `spend` benchmark: a base cost plus one for each input, which is
+/// read, signature checked and removed, one for each output, which is written, and the size term.
+impl WeightInfo for () {
+    fn spend(inputs: u32, outputs: u32, bytes: u32) -> Weight {
+        (10_000 as Weight)
+            .saturating_add((2_000 as Weight).saturating_mul(inputs as Weight))
+            .saturating_add((1_000 as Weight).saturating_mul(outputs as Weight))
+            .saturating_add((10 as Weight).saturating_mul(bytes as Weight))
+    }
+}
+
+/// Decides whether a transaction may be considered at all.
+pub trait TransactionFilter {
+    /// `Err` rejects `transaction` with the given reason.
+    fn allow(transaction: &Transaction) -> Result<(), &'static str>;
+}
+
+/// Allows every transaction.
+impl TransactionFilter for () {
+    fn allow(_transaction: &Transaction) -> Result<(), &'static str> {
+        Ok(())
+    }
+}
+
+/// Reacts to transactions once their effects are in storage.
+pub trait OnTransactionApplied {
+    /// `spent` are the consumed out points and `created` the out points of the new UTXOs.
+    fn on_applied(txid: H256, spent: &[H256], created: &[H256]);
+}
+
+impl OnTransactionApplied for () {
+    fn on_applied(_txid: H256, _spent: &[H256], _created: &[H256]) {}
+}
+
+/// The state validating a transaction reads besides the outputs it spends: chain storage, or a
+/// copy of it such as `BlockBodyState`.
+pub trait UtxoState {
+    /// The height the transaction is validated at.
+    fn block_height(&self) -> u64;
+    fn contains_utxo(&self, out_point: &H256) -> bool;
+    fn guarded_output(&self, out_point: &H256) -> Option<GuardedOutput>;
+    fn is_frozen(&self, out_point: &H256) -> bool;
+    fn gov_flag(&self, flag: &H256) -> bool;
+    /// Whether `key` is a burn address besides `BURN_ADDRESS`.
+    fn is_extra_burn_address(&self, key: &H256) -> bool;
+    /// The block the reward UTXO at `out_point` was minted in.
+    fn minted_at(&self, out_point: &H256) -> Option<u64>;
+    fn session_key(&self, owner: &H256) -> Option<(H256, u64)>;
+    /// The total native value of `key`'s UTXOs.
+    fn balance_of(&self, key: &H256) -> Value;
+    /// How many UTXOs `key` owns, of every asset.
+    fn utxo_count(&self, key: &H256) -> u32;
+
+    /// Whether outputs to `key` are burned.
+    fn is_burn_address(&self, key: &H256) -> bool {
+        *key == BURN_ADDRESS || self.is_extra_burn_address(key)
+    }
+
+    /// Whether `output` is added to the UTXO set, rather than burned or paid as a fee.
+    fn creates_utxo(&self, output: &TransactionOutput) -> bool {
+        !self.is_burn_address(&output.pub_key) && output.pub_key != FEE_ADDRESS
+    }
+}
+
+/// `UtxoState` read from the module's storage.
+struct StoredState<T>(sp_std::marker::PhantomData<T>);
+
+impl<T: Trait> UtxoState for StoredState<T> {
+    fn block_height(&self) -> u64 {
+        Module::<T>::block_height()
+    }
+
+    fn contains_utxo(&self, out_point: &H256) -> bool {
+        <UtxoStore>::contains_key(out_point)
+    }
+
+    fn guarded_output(&self, out_point: &H256) -> Option<GuardedOutput> {
+        <GuardedUtxos>::get(out_point)
+    }
+
+    fn is_frozen(&self, out_point: &H256) -> bool {
+        <FrozenUtxos>::get(out_point)
+    }
+
+    fn gov_flag(&self, flag: &H256) -> bool {
+        <GovFlags>::get(flag)
+    }
+
+    fn is_extra_burn_address(&self, key: &H256) -> bool {
+        <BurnAddresses>::get(key)
+    }
+
+    fn minted_at(&self, out_point: &H256) -> Option<u64> {
+        <CoinbaseUtxos>::get(out_point)
+    }
+
+    fn session_key(&self, owner: &H256) -> Option<(H256, u64)> {
+        <SessionKeys>::get(owner)
+    }
+
+    fn balance_of(&self, key: &H256) -> Value {
+        <BalanceIndex>::get(key)
+    }
+
+    fn utxo_count(&self, key: &H256) -> u32 {
+        <OwnerUtxos>::decode_len(key).unwrap_or(0) as u32
+    }
+}
+
+/// A copy of the chain state `apply_block_body` applies a block to, as it stands before the
+/// block. Balances and UTXO counts are worked out from `utxos`.
+#[cfg(feature = "std")]
+#[derive(PartialEq, Eq, Clone, Default, Debug)]
+pub struct BlockBodyState {
+    /// The height of the block being applied.
+    pub height: u64,
+    pub utxos: BTreeMap<H256, TransactionOutput>,
+    pub guarded: BTreeMap<H256, GuardedOutput>,
+    pub frozen: std::collections::BTreeSet<H256>,
+    /// The governance flags that are set.
+    pub gov_flags: std::collections::BTreeSet<H256>,
+    /// Burn addresses besides `BURN_ADDRESS`.
+    pub burn_addresses: std::collections::BTreeSet<H256>,
+    /// The block each reward UTXO was minted in, as in `CoinbaseUtxos`.
+    pub minted_at: BTreeMap<H256, u64>,
+    pub session_keys: BTreeMap<H256, (H256, u64)>,
+}
+
+#[cfg(feature = "std")]
+impl UtxoState for BlockBodyState {
+    fn block_height(&self) -> u64 {
+        self.height
+    }
+
+    fn contains_utxo(&self, out_point: &H256) -> bool {
+        self.utxos.contains_key(out_point)
+    }
+
+    fn guarded_output(&self, out_point: &H256) -> Option<GuardedOutput> {
+        self.guarded.get(out_point).cloned()
+    }
+
+    fn is_frozen(&self, out_point: &H256) -> bool {
+        self.frozen.contains(out_point)
+    }
+
+    fn gov_flag(&self, flag: &H256) -> bool {
+        self.gov_flags.contains(flag)
+    }
+
+    fn is_extra_burn_address(&self, key: &H256) -> bool {
+        self.burn_addresses.contains(key)
+    }
+
+    fn minted_at(&self, out_point: &H256) -> Option<u64> {
+        self.minted_at.get(out_point).cloned()
+    }
+
+    fn session_key(&self, owner: &H256) -> Option<(H256, u64)> {
+        self.session_keys.get(owner).cloned()
+    }
+
+    fn balance_of(&self, key: &H256) -> Value {
+        self.utxos.values()
+            .filter( |utxo| utxo.pub_key == *key && utxo.asset_id == NATIVE_ASSET )
+            .fold(0, |balance: Value, utxo| balance.saturating_add(utxo.value))
+    }
+
+    fn utxo_count(&self, key: &H256) -> u32 {
+        self.utxos.values().filter( |utxo| utxo.pub_key == *key ).count() as u32
+    }
 }
 
 #[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
@@ -23,15 +367,46 @@ pub trait Trait: frame_system::Trait {
 pub struct TransactionInput {
     pub out_point: H256,
     pub sig_script: H512,
+    /// The guardian's signature, when spending a `GuardedOutput`.
+    pub guardian_sig: Option<H512>,
 }
 
 pub type Value = u128;
 
+/// Identifies the asset an output carries.
+pub type AssetId = u32;
+
+/// The chain's native asset, used for fees and rewards.
+pub const NATIVE_ASSET: AssetId = 0;
+
+/// Outputs to this key are burned instead of being added to the UTXO set. Governance may add
+/// more burn addresses to `BurnAddresses`.
+pub const BURN_ADDRESS: H256 = H256::zero();
+
+/// Outputs to this key are an explicit fee, added to the block reward instead of the UTXO set.
+pub const FEE_ADDRESS: H256 = H256([0xff; 32]);
+
 #[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
 #[derive(PartialEq, Eq, PartialOrd, Ord, Default, Clone, Encode, Decode, Hash, Debug)]
 pub struct TransactionOutput {
     pub value: Value,
     pub pub_key: H256,
+    /// The output can't be spent before this block height.
+    pub lock_height: u64,
+    pub asset_id: AssetId,
+    /// A free-form note, e.g. a label a wallet shows. It doesn't affect spending.
+    pub metadata: Vec<u8>,
+    /// A note encrypted to `pub_key` with `encrypt_memo`. It isn't interpreted on chain.
+    pub memo: Vec<u8>,
+    /// The governance flag that must be set before the output can be spent, if any.
+    pub requires_gov_flag: Option<H256>,
+}
+
+/// A UTXO as stored before `StorageVersion` 1.
+#[derive(Encode, Decode)]
+struct LegacyTransactionOutput {
+    value: Value,
+    pub_key: H256,
 }
 
 #[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
@@ -39,11 +414,548 @@ pub struct TransactionOutput {
 pub struct Transaction {
     pub inputs: Vec<TransactionInput>,
     pub outputs: Vec<TransactionOutput>,
+    /// Distinguishes otherwise identical transactions. It's covered by the signatures and salts
+    /// the out points of the outputs, so neither transaction can replay the other.
+    pub nonce: u64,
+    /// How urgent the sender says the transaction is. It orders transactions paying the same fee
+    /// rate in the pool, but never ahead of a higher fee rate.
+    pub priority_hint: u8,
+}
+
+/// Native value that anyone knowing the preimage of `secret_hash` can redeem.
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Default, Clone, Encode, Decode, Hash, Debug)]
+pub struct HashLockOutput {
+    pub value: Value,
+    pub secret_hash: H256,
+}
+
+/// Native value that needs both `owner`'s and `guardian`'s signatures to spend, e.g. for custody
+/// or social recovery. It's spent as a transaction input carrying a `guardian_sig`.
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Default, Clone, Encode, Decode, Hash, Debug)]
+pub struct GuardedOutput {
+    pub value: Value,
+    pub owner: H256,
+    pub guardian: H256,
+}
+
+/// What a transaction spent at one of its inputs.
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+#[derive(PartialEq, Eq, Clone, Encode, Decode, Debug)]
+pub enum SpentOutput {
+    Utxo(TransactionOutput),
+    Guarded(GuardedOutput),
+}
+
+/// An input a transaction spent, with the per-UTXO records spending it cleared.
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+#[derive(PartialEq, Eq, Clone, Encode, Decode, Debug)]
+pub struct SpentUtxo {
+    pub out_point: H256,
+    pub output: SpentOutput,
+    /// See `UtxoCreator`.
+    pub creator: Option<H256>,
+    /// The block it was minted in, if it was a reward. See `CoinbaseUtxos`.
+    pub minted_at: Option<u64>,
+    pub frozen: bool,
+}
+
+/// What applying a transaction changed, kept for `RollbackWindow` blocks.
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+#[derive(PartialEq, Eq, Clone, Encode, Decode, Debug)]
+pub struct AppliedTransaction {
+    /// The block it was applied in.
+    pub block: u64,
+    pub spent: Vec<SpentUtxo>,
+    /// The out points of the UTXOs it created.
+    pub created: Vec<H256>,
+    /// The native value it left to the block authors.
+    pub fee: Value,
+    /// The asset and value of each output it burned.
+    pub burned: Vec<(AssetId, Value)>,
+}
+
+/// Native value held for `recipient` to claim before `expires_at`, after which it's refunded to
+/// `fallback_key`.
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Default, Clone, Encode, Decode, Hash, Debug)]
+pub struct Airdrop {
+    pub value: Value,
+    pub recipient: H256,
+    pub fallback_key: H256,
+    pub expires_at: u64,
+}
+
+/// What `scan_integrity` found wrong with a `UtxoStore` entry.
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+#[derive(PartialEq, Eq, Clone, Copy, Encode, Decode, Debug)]
+pub enum IntegrityFault {
+    /// The entry's key isn't an out point, or its value isn't a `TransactionOutput`. A bad key
+    /// is reported as the zero out point.
+    Undecodable,
+    /// The UTXO carries no value.
+    ZeroValue,
+    /// The UTXO belongs to a burn address or `FEE_ADDRESS`, which never receive UTXOs.
+    UnownableKey,
+    /// The UTXO is missing from its owner's `OwnerUtxos`.
+    NotIndexed,
+}
+
+/// Decode a transaction, accepting only the one encoding `encode` produces for it. Every length
+/// prefix must be minimal and nothing may follow the transaction, so no two byte strings decode
+/// to the same transaction.
+pub fn decode_canonical_transaction(encoded: &[u8]) -> Result<Transaction, &'static str> {
+    check_minimal_lengths(encoded)?;
+    let mut input = encoded;
+    let transaction = Transaction::decode(&mut input).map_err( |_| "invalid transaction encoding" )?;
+    ensure!(input.is_empty(), "trailing bytes after transaction");
+    ensure!(transaction.encode() == encoded, "non-canonical transaction encoding");
+    Ok(transaction)
+}
+
+/// Walk an encoded transaction, checking each length prefix is minimally encoded. Fixed-width
+/// fields like `Value` only have one encoding.
+fn check_minimal_lengths(mut encoded: &[u8]) -> Result<(), &'static str> {
+    let input = &mut encoded;
+    for _ in 0..read_minimal_length(input)? {
+        // out_point and sig_script
+        skip_bytes(input, 32 + 64)?;
+        // guardian_sig
+        match input.first() {
+            Some(0) => skip_bytes(input, 1)?,
+            Some(1) => skip_bytes(input, 1 + 64)?,
+            _ => return Err("invalid transaction encoding"),
+        }
+    }
+    for _ in 0..read_minimal_length(input)? {
+        // value, pub_key, lock_height and asset_id
+        skip_bytes(input, 16 + 32 + 8 + 4)?;
+        // metadata and memo
+        for _ in 0..2 {
+            let len = read_minimal_length(input)?;
+            skip_bytes(input, len)?;
+        }
+        // requires_gov_flag
+        match input.first() {
+            Some(0) => skip_bytes(input, 1)?,
+            Some(1) => skip_bytes(input, 1 + 32)?,
+            _ => return Err("invalid transaction encoding"),
+        }
+    }
+    // nonce and priority_hint
+    skip_bytes(input, 8 + 1)?;
+    Ok(())
+}
+
+fn read_minimal_length(input: &mut &[u8]) -> Result<usize, &'static str> {
+    let available = input.len();
+    let len = <Compact<u32>>::decode(input).map_err( |_| "invalid length prefix" )?.0;
+    ensure!(
+        available - input.len() == Compact::<u32>::compact_len(&len),
+        "non-minimal length prefix"
+    );
+    Ok(len as usize)
+}
+
+fn skip_bytes(input: &mut &[u8], count: usize) -> Result<(), &'static str> {
+    ensure!(input.len() >= count, "truncated transaction");
+    *input = &input[count..];
+    Ok(())
+}
+
+/// `to_compact` flag: every input carries the same signature, stored once.
+#[cfg(feature = "std")]
+const COMPACT_SHARED_SIG: u8 = 1;
+/// `to_compact` flag: some input has a `guardian_sig`, so every input stores its own.
+#[cfg(feature = "std")]
+const COMPACT_GUARDIAN_SIGS: u8 = 1 << 1;
+
+/// `to_compact` output flags, one per field that's stored only when it isn't the default.
+#[cfg(feature = "std")]
+const COMPACT_LOCK_HEIGHT: u8 = 1;
+#[cfg(feature = "std")]
+const COMPACT_ASSET_ID: u8 = 1 << 1;
+#[cfg(feature = "std")]
+const COMPACT_METADATA: u8 = 1 << 2;
+#[cfg(feature = "std")]
+const COMPACT_MEMO: u8 = 1 << 3;
+#[cfg(feature = "std")]
+const COMPACT_GOV_FLAG: u8 = 1 << 4;
+
+/// Encode `transaction` for the wire more tightly than SCALE: integers are compact, a signature
+/// shared by every input is stored once, and each output flags which of its optional fields are
+/// stored. `from_compact` reverses it.
+#[cfg(feature = "std")]
+pub fn to_compact(transaction: &Transaction) -> Vec<u8> {
+    let mut out = Vec::new();
+    let first_sig = transaction.inputs.first().map( |input| input.sig_script );
+    let shared_sig = transaction.inputs.iter().all( |input| Some(input.sig_script) == first_sig );
+    let guardian_sigs = transaction.inputs.iter().any( |input| input.guardian_sig.is_some() );
+    let mut header = 0;
+    if shared_sig { header |= COMPACT_SHARED_SIG }
+    if guardian_sigs { header |= COMPACT_GUARDIAN_SIGS }
+    out.push(header);
+    Compact(transaction.inputs.len() as u32).encode_to(&mut out);
+    for input in &transaction.inputs {
+        input.out_point.encode_to(&mut out);
+        if !shared_sig {
+            input.sig_script.encode_to(&mut out);
+        }
+        if guardian_sigs {
+            input.guardian_sig.encode_to(&mut out);
+        }
+    }
+    if let (true, Some(sig)) = (shared_sig, first_sig) {
+        sig.encode_to(&mut out);
+    }
+
+    Compact(transaction.outputs.len() as u32).encode_to(&mut out);
+    for output in &transaction.outputs {
+        let mut flags = 0;
+        if output.lock_height != 0 { flags |= COMPACT_LOCK_HEIGHT }
+        if output.asset_id != NATIVE_ASSET { flags |= COMPACT_ASSET_ID }
+        if !output.metadata.is_empty() { flags |= COMPACT_METADATA }
+        if !output.memo.is_empty() { flags |= COMPACT_MEMO }
+        if output.requires_gov_flag.is_some() { flags |= COMPACT_GOV_FLAG }
+        out.push(flags);
+        Compact(output.value).encode_to(&mut out);
+        output.pub_key.encode_to(&mut out);
+        if flags & COMPACT_LOCK_HEIGHT != 0 { Compact(output.lock_height).encode_to(&mut out) }
+        if flags & COMPACT_ASSET_ID != 0 { Compact(output.asset_id).encode_to(&mut out) }
+        if flags & COMPACT_METADATA != 0 { output.metadata.encode_to(&mut out) }
+        if flags & COMPACT_MEMO != 0 { output.memo.encode_to(&mut out) }
+        if let Some(flag) = output.requires_gov_flag { flag.encode_to(&mut out) }
+    }
+    Compact(transaction.nonce).encode_to(&mut out);
+    out.push(transaction.priority_hint);
+    out
+}
+
+/// Decode a transaction encoded by `to_compact`.
+#[cfg(feature = "std")]
+pub fn from_compact(encoded: &[u8]) -> Result<Transaction, &'static str> {
+    const INVALID: &str = "invalid compact transaction";
+    let input = &mut &encoded[..];
+    let header = u8::decode(input).map_err( |_| INVALID )?;
+    ensure!(header & !(COMPACT_SHARED_SIG | COMPACT_GUARDIAN_SIGS) == 0, INVALID);
+    let input_count = Compact::<u32>::decode(input).map_err( |_| INVALID )?.0;
+    let mut inputs = Vec::new();
+    for _ in 0..input_count {
+        let out_point = H256::decode(input).map_err( |_| INVALID )?;
+        let sig_script = if header & COMPACT_SHARED_SIG == 0 {
+            H512::decode(input).map_err( |_| INVALID )?
+        } else {
+            H512::zero()
+        };
+        let guardian_sig = if header & COMPACT_GUARDIAN_SIGS != 0 {
+            Option::<H512>::decode(input).map_err( |_| INVALID )?
+        } else {
+            None
+        };
+        inputs.push(TransactionInput { out_point, sig_script, guardian_sig });
+    }
+    if header & COMPACT_SHARED_SIG != 0 && input_count > 0 {
+        let sig = H512::decode(input).map_err( |_| INVALID )?;
+        inputs.iter_mut().for_each( |input| input.sig_script = sig );
+    }
+
+    let output_count = Compact::<u32>::decode(input).map_err( |_| INVALID )?.0;
+    let mut outputs = Vec::new();
+    for _ in 0..output_count {
+        let flags = u8::decode(input).map_err( |_| INVALID )?;
+        ensure!(flags < COMPACT_GOV_FLAG << 1, INVALID);
+        let present = |flag: u8| flags & flag != 0;
+        let mut output = TransactionOutput {
+            value: Compact::<Value>::decode(input).map_err( |_| INVALID )?.0,
+            pub_key: H256::decode(input).map_err( |_| INVALID )?,
+            ..Default::default()
+        };
+        if present(COMPACT_LOCK_HEIGHT) {
+            output.lock_height = Compact::<u64>::decode(input).map_err( |_| INVALID )?.0;
+        }
+        if present(COMPACT_ASSET_ID) {
+            output.asset_id = Compact::<AssetId>::decode(input).map_err( |_| INVALID )?.0;
+        }
+        if present(COMPACT_METADATA) {
+            output.metadata = Vec::decode(input).map_err( |_| INVALID )?;
+        }
+        if present(COMPACT_MEMO) {
+            output.memo = Vec::decode(input).map_err( |_| INVALID )?;
+        }
+        if present(COMPACT_GOV_FLAG) {
+            output.requires_gov_flag = Some(H256::decode(input).map_err( |_| INVALID )?);
+        }
+        outputs.push(output);
+    }
+    let nonce = Compact::<u64>::decode(input).map_err( |_| INVALID )?.0;
+    let priority_hint = u8::decode(input).map_err( |_| INVALID )?;
+    ensure!(input.is_empty(), "trailing bytes after transaction");
+    Ok(Transaction { inputs, outputs, nonce, priority_hint })
+}
+
+/// Parse a UTXO dump with one `pub_key,value` entry per line, `pub_key` being hex encoded,
+/// into entries for `import_external_utxos`. Blank lines are skipped.
+#[cfg(feature = "std")]
+pub fn parse_utxo_dump(dump: &str) -> Result<Vec<(H256, Value)>, String> {
+    dump.lines()
+        .enumerate()
+        .filter( |(_, line)| !line.trim().is_empty() )
+        .map( |(number, line)| {
+            let mut fields = line.split(',').map(str::trim);
+            let (pub_key, value) = match (fields.next(), fields.next(), fields.next()) {
+                (Some(pub_key), Some(value), None) => (pub_key, value),
+                _ => return Err(format!("line {}: expected `pub_key,value`", number + 1)),
+            };
+            let pub_key = parse_pub_key(pub_key).map_err( |e| format!("line {}: {}", number + 1, e) )?;
+            let value = value.parse::<Value>().map_err( |e| format!("line {}: invalid value: {}", number + 1, e) )?;
+            Ok((pub_key, value))
+        })
+        .collect()
+}
+
+/// Parse a genesis allocation with one `pub_key,value` entry per line, as in `parse_utxo_dump`,
+/// into native outputs for the `genesis_utxos` config. Each key may only appear once and every
+/// value must be nonzero.
+#[cfg(feature = "std")]
+pub fn parse_genesis_utxos(list: &str) -> Result<Vec<TransactionOutput>, String> {
+    let mut seen = std::collections::BTreeSet::new();
+    parse_utxo_dump(list)?
+        .into_iter()
+        .map( |(pub_key, value)| {
+            if !seen.insert(pub_key) {
+                return Err(format!("pub_key {:x} is allocated more than once", pub_key));
+            }
+            if value == 0 {
+                return Err(format!("pub_key {:x} is allocated a zero value", pub_key));
+            }
+            Ok(TransactionOutput {
+                value,
+                pub_key,
+                lock_height: 0,
+                asset_id: NATIVE_ASSET,
+                metadata: Vec::new(),
+                memo: Vec::new(),
+                requires_gov_flag: None,
+            })
+        })
+        .collect()
+}
+
+/// Parse a hex encoded `pub_key`, with or without a `0x` prefix.
+#[cfg(feature = "std")]
+pub fn parse_pub_key(hex: &str) -> Result<H256, String> {
+    parse_hash(hex).map_err( |e| format!("invalid pub_key: {}", e) )
+}
+
+#[cfg(feature = "std")]
+fn parse_hash(hex: &str) -> Result<H256, String> {
+    let hex = hex.trim_start_matches("0x");
+    if hex.len() != 64 {
+        return Err(format!("expected 64 hex digits, got {}", hex.len()));
+    }
+    hex.parse::<H256>().map_err( |e| format!("{:?}", e) )
+}
+
+/// Format an output as the `txid:vout` string block explorers expect.
+#[cfg(feature = "std")]
+pub fn format_outpoint(txid: H256, vout: u32) -> String {
+    format!("{:x}:{}", txid, vout)
+}
+
+/// Parse a `txid:vout` string made by `format_outpoint`.
+#[cfg(feature = "std")]
+pub fn parse_outpoint(outpoint: &str) -> Result<(H256, u32), String> {
+    let mut parts = outpoint.splitn(2, ':');
+    match (parts.next(), parts.next()) {
+        (Some(txid), Some(vout)) => {
+            let txid = parse_hash(txid).map_err( |e| format!("invalid txid: {}", e) )?;
+            let vout = vout.parse::<u32>().map_err( |e| format!("invalid vout: {}", e) )?;
+            Ok((txid, vout))
+        },
+        _ => Err("expected `txid:vout`".into()),
+    }
+}
+
+/// Format `pub_key` as an SS58 address for the network `prefix`, which must be below 64. The
+/// address carries a checksum, so `parse_address` catches mistyped ones.
+#[cfg(feature = "std")]
+pub fn format_address(pub_key: H256, prefix: u8) -> Result<String, String> {
+    check_network_prefix(prefix)?;
+    Ok(Public::from_h256(pub_key).to_ss58check_with_version(Ss58AddressFormat::Custom(prefix)))
+}
+
+/// Parse an address made by `format_address` for the network `prefix`.
+#[cfg(feature = "std")]
+pub fn parse_address(address: &str, prefix: u8) -> Result<H256, String> {
+    check_network_prefix(prefix)?;
+    let (public, format) = Public::from_ss58check_with_version(address)
+        .map_err( |e| format!("invalid address: {:?}", e) )?;
+    let network = u8::from(format);
+    if network != prefix {
+        return Err(format!("address is for network {}, expected {}", network, prefix));
+    }
+    Ok(H256::from(public.0))
+}
+
+/// Simple SS58 addresses spend one byte on the network, so only prefixes below 64 can be
+/// formatted and parsed back.
+#[cfg(feature = "std")]
+fn check_network_prefix(prefix: u8) -> Result<(), String> {
+    if prefix >= 64 {
+        return Err(format!("network prefix must be below 64, got {}", prefix));
+    }
+    Ok(())
+}
+
+/// A value or key in an `OutputTemplate`, either fixed or named and filled in by
+/// `TransactionTemplate::fill`.
+#[cfg(feature = "std")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TemplateSlot<T> {
+    Fixed(T),
+    Placeholder(String),
+}
+
+#[cfg(feature = "std")]
+impl<T: Clone> TemplateSlot<T> {
+    fn fill(&self, args: &BTreeMap<String, T>) -> Result<T, String> {
+        match self {
+            TemplateSlot::Fixed(fixed) => Ok(fixed.clone()),
+            TemplateSlot::Placeholder(name) => args.get(name).cloned()
+                .ok_or_else( || format!("placeholder `{}` isn't filled", name) ),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OutputTemplate {
+    pub value: TemplateSlot<Value>,
+    pub pub_key: TemplateSlot<H256>,
+    /// The rest of the output. Its `value` and `pub_key` are replaced.
+    pub base: TransactionOutput,
+}
+
+/// A transaction sent repeatedly with the same structure, e.g. a recurring payment. The inputs
+/// are given each time and outputs may leave their value or key as placeholders.
+#[cfg(feature = "std")]
+#[derive(Clone, Debug, PartialEq, Eq, Default)]
+pub struct TransactionTemplate {
+    pub outputs: Vec<OutputTemplate>,
+}
+
+#[cfg(feature = "std")]
+impl TransactionTemplate {
+    /// An unsigned transaction spending `inputs`, each placeholder taken from `values` or `keys`.
+    pub fn fill(
+        &self,
+        inputs: &[H256],
+        nonce: u64,
+        values: &BTreeMap<String, Value>,
+        keys: &BTreeMap<String, H256>,
+    ) -> Result<Transaction, String> {
+        let outputs = self.outputs.iter()
+            .map( |output| Ok(TransactionOutput {
+                value: output.value.fill(values)?,
+                pub_key: output.pub_key.fill(keys)?,
+                ..output.base.clone()
+            }))
+            .collect::<Result<_, String>>()?;
+        Ok(Transaction {
+            inputs: inputs.iter()
+                .map( |out_point| TransactionInput {
+                    out_point: *out_point,
+                    sig_script: H512::zero(),
+                    guardian_sig: None,
+                })
+                .collect(),
+            outputs,
+            nonce,
+            priority_hint: 0,
+        })
+    }
+}
+
+/// Bytes `encrypt_memo` adds to the plaintext: the ephemeral public key and the tag.
+#[cfg(feature = "std")]
+pub const MEMO_OVERHEAD: usize = 32 + 16;
+
+/// Encrypt `memo` so only the holder of `recipient`'s sr25519 secret key can read it, for use as
+/// an output's `memo`. `ephemeral_seed` must be random and never reused.
+#[cfg(feature = "std")]
+pub fn encrypt_memo(recipient: H256, memo: &[u8], ephemeral_seed: [u8; 32]) -> Result<Vec<u8>, String> {
+    let recipient = CompressedRistretto(recipient.0).decompress().ok_or("recipient is not an sr25519 key")?;
+    let ephemeral = Scalar::from_bytes_mod_order(sp_core::blake2_256(&ephemeral_seed));
+    let ephemeral_public = (&ephemeral * &RISTRETTO_BASEPOINT_TABLE).compress();
+    let shared = (ephemeral * recipient).compress();
+
+    let (cipher_key, mac_key) = memo_keys(&shared, &ephemeral_public);
+    let mut encrypted = ephemeral_public.as_bytes().to_vec();
+    encrypted.extend(apply_keystream(&cipher_key, memo));
+    let tag = memo_tag(&mac_key, &encrypted[32..]);
+    encrypted.extend_from_slice(&tag);
+    Ok(encrypted)
+}
+
+/// Decrypt a memo made by `encrypt_memo` with the recipient's key pair.
+#[cfg(feature = "std")]
+pub fn decrypt_memo(recipient: &sp_core::sr25519::Pair, encrypted: &[u8]) -> Result<Vec<u8>, String> {
+    use sp_core::Pair;
+
+    if encrypted.len() < MEMO_OVERHEAD {
+        return Err("memo too short".into());
+    }
+    let (ephemeral_public, rest) = encrypted.split_at(32);
+    let (ciphertext, tag) = rest.split_at(rest.len() - 16);
+    let ephemeral_public = CompressedRistretto::from_slice(ephemeral_public);
+    let ephemeral_point = ephemeral_public.decompress().ok_or("invalid ephemeral key")?;
+
+    let mut scalar_bytes = [0u8; 32];
+    scalar_bytes.copy_from_slice(&recipient.to_raw_vec()[..32]);
+    let secret = Scalar::from_canonical_bytes(scalar_bytes).ok_or("invalid secret key")?;
+    let shared = (secret * ephemeral_point).compress();
+
+    let (cipher_key, mac_key) = memo_keys(&shared, &ephemeral_public);
+    if memo_tag(&mac_key, ciphertext)[..] != tag[..] {
+        return Err("memo was not encrypted to this key".into());
+    }
+    Ok(apply_keystream(&cipher_key, ciphertext))
+}
+
+#[cfg(feature = "std")]
+fn memo_keys(shared: &CompressedRistretto, ephemeral_public: &CompressedRistretto) -> ([u8; 32], [u8; 32]) {
+    let derive = |label: &[u8]| {
+        sp_core::blake2_256(&[label, &shared.as_bytes()[..], &ephemeral_public.as_bytes()[..]].concat())
+    };
+    (derive(b"utxo-memo-cipher"), derive(b"utxo-memo-mac"))
+}
+
+#[cfg(feature = "std")]
+fn apply_keystream(key: &[u8; 32], data: &[u8]) -> Vec<u8> {
+    data.chunks(32)
+        .enumerate()
+        .flat_map( |(block, chunk)| {
+            let pad = sp_core::blake2_256(&[&key[..], &(block as u64).to_le_bytes()[..]].concat());
+            chunk.iter().zip(pad.iter()).map( |(byte, pad)| byte ^ pad ).collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+#[cfg(feature = "std")]
+fn memo_tag(key: &[u8; 32], ciphertext: &[u8]) -> [u8; 16] {
+    let mut tag = [0u8; 16];
+    tag.copy_from_slice(&sp_core::blake2_256(&[&key[..], ciphertext].concat())[..16]);
+    tag
 }
 
 decl_storage! {
     trait Store for Module<T: Trait> as Utxo {
-        UtxoStore build(|config: &GenesisConfig| {
+        UtxoStore get(fn get_utxo) build(|config: &GenesisConfig| {
+            Module::<T>::check_genesis_asset_supplies(&config.genesis_utxos, &config.asset_supplies);
+            Module::<T>::check_genesis_native_supply(
+                &config.genesis_utxos, config.initial_reward, config.total_genesis_supply
+            );
             config.genesis_utxos
             .iter()
             .cloned()
@@ -51,55 +963,1059 @@ decl_storage! {
             .collect::<Vec<_>>()
         }): map hasher(identity) H256 => Option<TransactionOutput>;
 
-        pub RewardTotal get(fn reward_total) : Value;
+        /// The owner of the first input of the transaction that created each UTXO.
+        /// Genesis, reward and minted UTXOs have no creator.
+        pub UtxoCreator get(fn creator_of): map hasher(identity) H256 => Option<H256>;
+
+        /// The total native value of each key's UTXOs.
+        pub BalanceIndex get(fn balance_of) build(|config: &GenesisConfig| {
+            let mut balances: BTreeMap<H256, Value> = BTreeMap::new();
+            for utxo in config.genesis_utxos.iter().filter( |u| u.asset_id == NATIVE_ASSET ) {
+                let balance = balances.entry(utxo.pub_key).or_default();
+                *balance = balance.checked_add(utxo.value).expect("genesis balance overflow");
+            }
+            balances.into_iter().collect::<Vec<_>>()
+        }): map hasher(blake2_128_concat) H256 => Value;
+
+        /// The out points of each key's UTXOs, of every asset.
+        pub OwnerUtxos get(fn owner_utxos) build(|config: &GenesisConfig| {
+            let mut owned: BTreeMap<H256, Vec<H256>> = BTreeMap::new();
+            for utxo in config.genesis_utxos.iter() {
+                owned.entry(utxo.pub_key).or_default().push(BlakeTwo256::hash_of(utxo));
+            }
+            owned.into_iter().collect::<Vec<_>>()
+        }): map hasher(blake2_128_concat) H256 => Vec<H256>;
+
+        /// Reward UTXOs and the block they were minted in.
+        CoinbaseUtxos: map hasher(identity) H256 => Option<u64>;
+
+        pub RewardTotal get(fn reward_total) build(|config: &GenesisConfig| config.initial_reward): Value;
+
+        /// Fees of rolled back transactions that had already been paid out, withheld from the
+        /// next block rewards.
+        pub RewardDebt get(fn reward_debt): Value;
+
+        /// Whether `RewardTotalHigh` has been emitted since `RewardTotal` was last at or below
+        /// `RewardTotalHighWater`.
+        RewardTotalHighWarned: bool;
+
+        /// How much of `RewardTotal` was carried over from earlier blocks, and the authority set
+        /// that earned it.
+        CarriedReward: Value;
+        RewardEarners: Vec<H256>;
+
+        /// The block each current authority was first seen in the set.
+        AuthorityFirstSeen: map hasher(blake2_128_concat) H256 => Option<u64>;
+
+        /// The block since which `RewardTotal` has been too small to give every authority a
+        /// share, if it still is.
+        RewardDustSince: Option<u64>;
+
+        /// The reward each authority received in a block, for the last `RewardHistoryRetention`
+        /// blocks.
+        pub BlockRewards get(fn rewards_in_block): map hasher(twox_64_concat) u64 => Vec<(H256, Value)>;
+
+        /// The fees collected in each block before dispersal, for the same blocks as
+        /// `BlockRewards`. Blocks without fees have no entry.
+        BlockFees: map hasher(twox_64_concat) u64 => Value;
+
+        /// Each authority's latest reward UTXO and the block it was minted in, kept while
+        /// reward merging or pooling is enabled.
+        LastReward: map hasher(blake2_128_concat) H256 => Option<(H256, u64)>;
+
+        /// Total value removed from circulation.
+        pub BurnedTotal get(fn burned_total) : Value;
+
+        /// Burn addresses besides `BURN_ADDRESS`.
+        pub BurnAddresses get(fn is_extra_burn_address): map hasher(blake2_128_concat) H256 => bool;
+
+        /// Circulating supply of each non-native asset.
+        pub AssetSupply get(fn asset_supply) build(|config: &GenesisConfig| {
+            config.asset_supplies
+            .iter()
+            .map( |(asset_id, supply)| (*asset_id, *supply) )
+            .collect::<Vec<_>>()
+        }): map hasher(twox_64_concat) AssetId => Value;
+
+        /// Outputs redeemable with a secret rather than a signature.
+        pub HashLockStore get(fn hash_lock): map hasher(identity) H256 => Option<HashLockOutput>;
+
+        /// Redemption commitments and the block they were made in.
+        RedeemCommitments: map hasher(identity) H256 => Option<u64>;
+
+        /// Out points consumed by `spend` in the current block.
+        SpentThisBlock: map hasher(identity) H256 => bool;
+
+        /// Transactions applied in the last `RollbackWindow` blocks, by txid, so
+        /// `rollback_transaction` can undo them.
+        pub AppliedTransactions get(fn applied_transaction): map hasher(identity) H256 => Option<AppliedTransaction>;
+
+        /// The txids in `AppliedTransactions` by the block they were applied in.
+        AppliedInBlock: map hasher(twox_64_concat) u64 => Vec<H256>;
+
+        /// A key each owner has authorized to sign `spend` inputs on its behalf, and the last
+        /// block height the authorization holds for.
+        pub SessionKeys get(fn session_key): map hasher(blake2_128_concat) H256 => Option<(H256, u64)>;
+
+        /// The expiry of each owner's last revoked session key. Later session keys must expire
+        /// after it, so the revoked authorization can't be replayed.
+        RevokedSessionExpiry: map hasher(blake2_128_concat) H256 => u64;
+
+        /// The native value each key has bonded, weighting its share of block rewards.
+        pub Stake get(fn stake_of): map hasher(blake2_128_concat) H256 => Value;
+
+        /// Bonded UTXOs, out of the UTXO set until they're withdrawn.
+        pub LockedUtxos get(fn locked_utxo): map hasher(identity) H256 => Option<TransactionOutput>;
+
+        /// Unbonded UTXOs and the block height they can be withdrawn from.
+        UnbondingUtxos: map hasher(identity) H256 => Option<u64>;
+
+        /// UTXOs governance has frozen. They can't be spent or otherwise moved.
+        pub FrozenUtxos get(fn is_frozen): map hasher(identity) H256 => bool;
+
+        /// Named flags set by governance, gating outputs with `requires_gov_flag`.
+        pub GovFlags get(fn gov_flag): map hasher(blake2_128_concat) H256 => bool;
+
+        /// Outputs needing their guardian's co-signature to spend.
+        pub GuardedUtxos get(fn guarded_output): map hasher(identity) H256 => Option<GuardedOutput>;
+
+        /// Unclaimed airdrops.
+        pub AirdropStore get(fn airdrop): map hasher(identity) H256 => Option<Airdrop>;
+
+        /// Airdrops by the block they expire in.
+        AirdropExpiries: map hasher(twox_64_concat) u64 => Vec<H256>;
+
+        /// Where the airdrop expiry sweep resumes: the expiry height and how many of its entries
+        /// are done.
+        AirdropSweepCursor: Option<(u64, u32)>;
+
+        /// The storage key of the last entry `scan_integrity` checked, if a scan is under way.
+        IntegrityScanCursor: Option<Vec<u8>>;
+
+        /// UTXOs added or removed in the current block, when `EmitStateDelta` is set: whether each
+        /// existed before the block, and what it holds now.
+        BlockStateChanges: map hasher(identity) H256 => Option<(bool, Option<TransactionOutput>)>;
+
+        /// Fee rates of the transactions included in the current block.
+        BlockFeeRates: Vec<u64>;
+
+        /// Median fee rates of the last `FeeRateWindow` blocks with transactions, oldest first.
+        pub RecentFeeRates get(fn recent_fee_rates): Vec<u64>;
+
+        /// Number of successful `spend` calls in the current block.
+        BlockTransactionCount: u32;
+
+        /// Whether `import_external_utxos` has been used.
+        pub ExternalImportDone get(fn external_import_done) : bool;
+
+        /// Inclusive block ranges during which `spend` is not accepted.
+        pub MaintenanceWindows get(fn maintenance_windows) : Vec<(T::BlockNumber, T::BlockNumber)>;
+
+        /// The layout of `UtxoStore`: 0 for the original `value` and `pub_key` outputs, 1 for
+        /// `TransactionOutput` as it is now.
+        StorageVersion build(|_: &GenesisConfig| 1u32): u32;
     }
 
     add_extra_genesis {
         config(genesis_utxos): Vec<TransactionOutput>;
+        /// The total genesis supply of each non-native asset.
+        config(asset_supplies): BTreeMap<AssetId, Value>;
+        /// Native value held for the first block's authors.
+        config(initial_reward): Value;
+        /// What the native genesis outputs and `initial_reward` must add up to.
+        config(total_genesis_supply): Value;
     }
 }
 
 // External functions: callable by the end user
 decl_module! {
     pub struct Module<T: Trait> for enum Call where origin: T::Origin {
+        const MaxRewardRemainder: Value = T::MaxRewardRemainder::get();
+        const MinOutputs: u32 = T::MinOutputs::get();
+        const RewardMergeWindow: u32 = T::RewardMergeWindow::get();
+        const DustThreshold: Value = T::DustThreshold::get();
+        const MaxTransactionsPerBlock: u32 = T::MaxTransactionsPerBlock::get();
+        const RewardDecay: Permill = T::RewardDecay::get();
+        const MaxMetadataLength: u32 = T::MaxMetadataLength::get();
+        const StrictBalance: bool = T::StrictBalance::get();
+        const RewardHistoryRetention: u32 = T::RewardHistoryRetention::get();
+        const CoinbaseMaturity: u32 = T::CoinbaseMaturity::get();
+        const FeeRateWindow: u32 = T::FeeRateWindow::get();
+        const MaxBalancePerKey: Value = T::MaxBalancePerKey::get();
+        const MaxUtxosPerOwner: u32 = T::MaxUtxosPerOwner::get();
+        const MinimumFee: Value = T::MinimumFee::get();
+        const ConsolidationDiscount: Permill = T::ConsolidationDiscount::get();
+        const MaxMemoLength: u32 = T::MaxMemoLength::get();
+        const MinInputValue: Value = T::MinInputValue::get();
+        const MaxFee: Value = T::MaxFee::get();
+        const RewardEpochLength: u32 = T::RewardEpochLength::get();
+        const UnbondingPeriod: u32 = T::UnbondingPeriod::get();
+        const RewardTotalHighWater: Value = T::RewardTotalHighWater::get();
+        const MaxSweepPerBlock: u32 = T::MaxSweepPerBlock::get();
+        const BatchVerifyThreshold: u32 = T::BatchVerifyThreshold::get();
+        const RewardDustIdlePeriod: u32 = T::RewardDustIdlePeriod::get();
+        const RewardDustKey: H256 = T::RewardDustKey::get();
+        const MinRewardUptime: u32 = T::MinRewardUptime::get();
+        const RewardDenomination: Value = T::RewardDenomination::get();
+        const MaxAssetsPerTransaction: u32 = T::MaxAssetsPerTransaction::get();
+        const RewardReorgWindow: u32 = T::RewardReorgWindow::get();
+        const FeePerNewOutput: Value = T::FeePerNewOutput::get();
+        const RewardVestingPeriod: u32 = T::RewardVestingPeriod::get();
+        const RewardVestingTranches: u32 = T::RewardVestingTranches::get();
+        const RewardCliffBlocks: u32 = T::RewardCliffBlocks::get();
+        const ConsolidateChange: bool = T::ConsolidateChange::get();
+        const EmitStateDelta: bool = T::EmitStateDelta::get();
+        const RollbackWindow: u32 = T::RollbackWindow::get();
+
         fn deposit_event() = default;
 
-        #[weight = 10_000]
+        #[weight = T::WeightInfo::spend(
+            transaction.inputs.len() as u32,
+            transaction.outputs.len() as u32,
+            transaction.encode().len() as u32,
+        )]
         pub fn spend(_origin, transaction: Transaction) -> DispatchResult {
-            let valid_transaction = Self::validate_transaction(&transaction)?;
-            Self::update_storage(&transaction, valid_transaction.priority as Value)?;
-            Self::deposit_event(Event::TransactionSuccess(transaction));
+            let now = <frame_system::Module<T>>::block_number();
+            ensure!(
+                !<MaintenanceWindows<T>>::get().iter().any( |(start, end)| *start <= now && now <= *end ),
+                "outside acceptance window"
+            );
+            Self::apply_transaction(transaction, false)
+        }
+
+        /// Apply `transaction` ignoring its inputs' freezes, governance flags and time-locks, to
+        /// recover funds stuck behind a faulty lock. Signatures and balances are checked as usual.
+        #[weight = T::WeightInfo::spend(
+            transaction.inputs.len() as u32,
+            transaction.outputs.len() as u32,
+            transaction.encode().len() as u32,
+        )]
+        pub fn force_spend(origin, transaction: Transaction) -> DispatchResult {
+            T::ForceOrigin::ensure_origin(origin)?;
+            Self::apply_transaction(transaction, true)
+        }
+
+        /// Re-create a UTXO for the same owner and value with a later `lock_height`.
+        /// `sig` is the owner's signature over `(b"refresh", out_point, new_lock_height)`.
+        #[weight = 10_000]
+        pub fn refresh(_origin, out_point: H256, new_lock_height: u64, sig: H512) -> DispatchResult {
+            let utxo = <UtxoStore>::get(&out_point).ok_or("input utxo not found")?;
+            ensure!(!<FrozenUtxos>::get(&out_point), "input utxo is frozen");
+            ensure!(sp_io::crypto::sr25519_verify(
+                &Signature::from_raw(*sig.as_fixed_bytes()),
+                &(&b"refresh"[..], &out_point, new_lock_height).encode(),
+                &Public::from_h256(utxo.pub_key)
+            ), "signature must be valid");
+            ensure!(new_lock_height >= Self::block_height(), "lock height must not be in the past");
+            ensure!(new_lock_height >= utxo.lock_height, "lock height must not decrease");
+
+            let new_utxo = TransactionOutput {
+                lock_height: new_lock_height,
+                ..utxo
+            };
+            let hash = BlakeTwo256::hash_of( &(&out_point, new_lock_height) );
+            ensure!(! <UtxoStore>::contains_key(hash), "output already exists" );
+
+            let creator = <UtxoCreator>::get(out_point);
+            let coinbase_height = <CoinbaseUtxos>::get(out_point);
+            Self::remove_utxo(&out_point);
+            Self::insert_utxo(hash, new_utxo);
+            if let Some(creator) = creator {
+                <UtxoCreator>::insert(hash, creator);
+            }
+            // Refreshing a reward doesn't reset its maturity.
+            if let Some(height) = coinbase_height {
+                <CoinbaseUtxos>::insert(hash, height);
+            }
+            Self::deposit_event(Event::UtxoRefreshed(out_point, hash));
+            Ok(())
+        }
+
+        /// Mint the reward carried between blocks to `RewardDustKey` once it has been too small to
+        /// disperse for `RewardDustIdlePeriod` blocks. Fees collected since the last dispersal stay
+        /// in `RewardTotal` for the authorities. Anyone may call this.
+        #[weight = 10_000]
+        pub fn sweep_reward_dust(_origin) -> DispatchResult {
+            let now = Self::block_height();
+            let since = <RewardDustSince>::get().ok_or("no reward dust to sweep")?;
+            ensure!(
+                now >= since.saturating_add(T::RewardDustIdlePeriod::get() as u64),
+                "reward dust hasn't been idle long enough"
+            );
+            let dust = <CarriedReward>::get().min(<RewardTotal>::get());
+            ensure!(dust > 0, "no reward dust to sweep");
+            let key = T::RewardDustKey::get();
+            Self::ensure_owner_capacity(&key, 1)?;
+            let utxo = TransactionOutput {
+                value: dust,
+                pub_key: key,
+                lock_height: 0,
+                asset_id: NATIVE_ASSET,
+                metadata: Vec::new(),
+                memo: Vec::new(),
+                requires_gov_flag: None,
+            };
+            let hash = BlakeTwo256::hash_of( &(&b"reward dust"[..], &utxo, now) );
+            ensure!(! <UtxoStore>::contains_key(hash), "output already exists" );
+
+            Self::insert_utxo(hash, utxo);
+            let remaining = <RewardTotal>::get() - dust;
+            <RewardTotal>::put(remaining);
+            <CarriedReward>::kill();
+            <RewardEarners>::kill();
+            <RewardDustSince>::kill();
+            Self::check_reward_high_water(remaining);
+            Self::deposit_event(Event::RewardDustSwept(hash, dust));
+            Ok(())
+        }
+
+        /// Add `key` to the burn addresses, or remove it. UTXOs it already owns can't be spent
+        /// while it's a burn address. `BURN_ADDRESS` itself can't be removed.
+        #[weight = 10_000]
+        pub fn set_burn_address(origin, key: H256, burns: bool) -> DispatchResult {
+            ensure_root(origin)?;
+            ensure!(key != BURN_ADDRESS, "the default burn address is fixed");
+            ensure!(key != FEE_ADDRESS, "the fee address can't burn");
+            if burns {
+                <BurnAddresses>::insert(key, true);
+            } else {
+                <BurnAddresses>::remove(key);
+            }
+            Self::deposit_event(Event::BurnAddressChanged(key, burns));
+            Ok(())
+        }
+
+        /// Freeze or unfreeze the UTXO at `out_point`.
+        #[weight = 10_000]
+        pub fn set_frozen(origin, out_point: H256, frozen: bool) -> DispatchResult {
+            ensure_root(origin)?;
+            if frozen {
+                ensure!(<UtxoStore>::contains_key(&out_point), "utxo not found");
+                <FrozenUtxos>::insert(out_point, true);
+            } else {
+                <FrozenUtxos>::remove(out_point);
+            }
+            Self::deposit_event(Event::UtxoFrozen(out_point, frozen));
+            Ok(())
+        }
+
+        /// Set or clear a governance flag.
+        #[weight = 10_000]
+        pub fn set_gov_flag(origin, flag: H256, value: bool) -> DispatchResult {
+            ensure_root(origin)?;
+            if value {
+                <GovFlags>::insert(flag, true);
+            } else {
+                <GovFlags>::remove(flag);
+            }
+            Self::deposit_event(Event::GovFlagChanged(flag, value));
+            Ok(())
+        }
+
+        /// Replace the block ranges during which `spend` is rejected.
+        #[weight = 10_000]
+        pub fn set_maintenance_windows(origin, windows: Vec<(T::BlockNumber, T::BlockNumber)>) -> DispatchResult {
+            ensure_root(origin)?;
+            ensure!(windows.iter().all( |(start, end)| start <= end ), "window must not end before it starts");
+            <MaintenanceWindows<T>>::put(windows);
+            Ok(())
+        }
+
+        /// Turn a native UTXO into a voucher redeemable by whoever knows the preimage of
+        /// `secret_hash`. `sig` is the owner's signature over
+        /// `(b"voucher", out_point, secret_hash)`.
+        #[weight = 10_000]
+        pub fn create_voucher(_origin, out_point: H256, secret_hash: H256, sig: H512) -> DispatchResult {
+            let utxo = <UtxoStore>::get(&out_point).ok_or("input utxo not found")?;
+            ensure!(sp_io::crypto::sr25519_verify(
+                &Signature::from_raw(*sig.as_fixed_bytes()),
+                &(&b"voucher"[..], &out_point, secret_hash).encode(),
+                &Public::from_h256(utxo.pub_key)
+            ), "signature must be valid");
+            ensure!(utxo.asset_id == NATIVE_ASSET, "only native value can be hash-locked");
+            Self::is_spendable(&out_point, &utxo, Self::block_height(), false)?;
+
+            let voucher = HashLockOutput {
+                value: utxo.value,
+                secret_hash,
+            };
+            let hash = BlakeTwo256::hash_of( &(&out_point, &voucher) );
+            ensure!(! <HashLockStore>::contains_key(hash), "output already exists" );
+
+            Self::remove_utxo(&out_point);
+            <HashLockStore>::insert(hash, voucher);
+            Self::deposit_event(Event::VoucherCreated(hash));
+            Ok(())
+        }
+
+        /// Commit to redeeming a voucher, with `commitment` being the hash of
+        /// `(preimage, voucher, destination)`. Redemption is only possible in a later block, so
+        /// a preimage seen in the pool can't be redirected to another destination.
+        #[weight = 10_000]
+        pub fn commit_redeem(_origin, commitment: H256) -> DispatchResult {
+            ensure!(! <RedeemCommitments>::contains_key(commitment), "commitment already exists");
+            <RedeemCommitments>::insert(commitment, Self::block_height());
+            Ok(())
+        }
+
+        /// Redeem a voucher to `destination` by revealing the preimage of its secret hash.
+        #[weight = 10_000]
+        pub fn redeem_voucher(_origin, voucher: H256, preimage: Vec<u8>, destination: H256) -> DispatchResult {
+            let locked = <HashLockStore>::get(&voucher).ok_or("voucher not found")?;
+            ensure!(BlakeTwo256::hash(&preimage) == locked.secret_hash, "preimage does not match");
+
+            let commitment = BlakeTwo256::hash_of( &(&preimage, &voucher, &destination) );
+            let committed_at = <RedeemCommitments>::get(commitment).ok_or("redemption was not committed")?;
+            ensure!(committed_at < Self::block_height(), "redemption must be committed in an earlier block");
+            ensure!(!Self::is_burn_address(&destination), "can't redeem to the burn address");
+            ensure!(destination != FEE_ADDRESS, "can't redeem to the fee address");
+
+            let utxo = TransactionOutput {
+                value: locked.value,
+                pub_key: destination,
+                lock_height: 0,
+                asset_id: NATIVE_ASSET,
+                metadata: Vec::new(),
+                memo: Vec::new(),
+                requires_gov_flag: None,
+            };
+            let hash = BlakeTwo256::hash_of( &(&voucher, &destination) );
+            ensure!(! <UtxoStore>::contains_key(hash), "output already exists" );
+            Self::ensure_owner_capacity(&destination, 1)?;
+
+            <RedeemCommitments>::remove(commitment);
+            <HashLockStore>::remove(voucher);
+            Self::insert_utxo(hash, utxo);
+            Self::deposit_event(Event::VoucherRedeemed(voucher, hash));
+            Ok(())
+        }
+
+        /// Create `value` of a non-native asset owned by `pub_key`.
+        #[weight = 10_000]
+        pub fn mint_asset(origin, asset_id: AssetId, value: Value, pub_key: H256) -> DispatchResult {
+            ensure_root(origin)?;
+            ensure!(asset_id != NATIVE_ASSET, "native asset can't be minted");
+            ensure!(value > 0, "output value must be nonzero");
+            ensure!(!Self::is_burn_address(&pub_key), "can't mint to the burn address");
+            ensure!(pub_key != FEE_ADDRESS, "can't mint to the fee address");
+
+            let new_supply = <AssetSupply>::get(asset_id).checked_add(value).ok_or("asset supply overflow")?;
+            let utxo = TransactionOutput {
+                value,
+                pub_key,
+                lock_height: 0,
+                asset_id,
+                metadata: Vec::new(),
+                memo: Vec::new(),
+                requires_gov_flag: None,
+            };
+            let hash = BlakeTwo256::hash_of( &(&utxo, Self::block_height(), new_supply) );
+            ensure!(! <UtxoStore>::contains_key(hash), "output already exists" );
+            Self::ensure_owner_capacity(&pub_key, 1)?;
+
+            Self::insert_utxo(hash, utxo);
+            <AssetSupply>::insert(asset_id, new_supply);
+            Self::deposit_event(Event::AssetSupplyChanged(asset_id, new_supply));
+            Ok(())
+        }
+
+        /// Create native UTXOs from another chain's UTXO set, treating each entry's hash as the
+        /// owner's `pub_key`. See `parse_utxo_dump`. Each UTXO's out point is the hash of
+        /// `(b"import", utxo, index)`. This can only be done once.
+        #[weight = 10_000]
+        pub fn import_external_utxos(origin, entries: Vec<(H256, Value)>) -> DispatchResult {
+            ensure_root(origin)?;
+            ensure!(!<ExternalImportDone>::get(), "external utxos already imported");
+
+            {
+                let entry_set: BTreeMap<_, ()> = entries.iter().map( |entry| (entry, ()) ).collect();
+                ensure!(entry_set.len() == entries.len(), "each entry must only be imported once");
+            }
+
+            let mut utxos = Vec::with_capacity(entries.len());
+            let mut per_owner: BTreeMap<H256, u32> = BTreeMap::new();
+            for (index, (pub_key, value)) in entries.iter().enumerate() {
+                ensure!(!Self::is_burn_address(pub_key), "can't import to the burn address");
+                ensure!(*pub_key != FEE_ADDRESS, "can't import to the fee address");
+                ensure!(*value > 0, "output value must be nonzero");
+                ensure!(*value >= T::DustThreshold::get(), "output value below dust threshold");
+                let utxo = TransactionOutput {
+                    value: *value,
+                    pub_key: *pub_key,
+                    lock_height: 0,
+                    asset_id: NATIVE_ASSET,
+                    metadata: Vec::new(),
+                    memo: Vec::new(),
+                    requires_gov_flag: None,
+                };
+                let hash = BlakeTwo256::hash_of( &(&b"import"[..], &utxo, index as u64) );
+                ensure!(! <UtxoStore>::contains_key(hash), "output already exists" );
+                *per_owner.entry(*pub_key).or_default() += 1;
+                utxos.push((hash, utxo));
+            }
+            for (pub_key, count) in per_owner.iter() {
+                Self::ensure_owner_capacity(pub_key, *count)?;
+            }
+
+            for (hash, utxo) in utxos {
+                Self::insert_utxo(hash, utxo);
+            }
+            <ExternalImportDone>::put(true);
+            Self::deposit_event(Event::ExternalUtxosImported(entries.len() as u32));
+            Ok(())
+        }
+
+        /// Undo a transaction applied in the last `RollbackWindow` blocks, as recorded in
+        /// `AppliedTransactions`: remove the UTXOs it created, which must all be unspent, and
+        /// restore the ones it spent along with their records, such as coinbase maturity. Its
+        /// burns are reversed and its fee taken back out of `RewardTotal`, or out of later
+        /// rewards through `RewardDebt` once it has been paid out.
+        #[weight = 10_000]
+        pub fn rollback_transaction(origin, txid: H256) -> DispatchResult {
+            ensure_root(origin)?;
+            let applied = <AppliedTransactions>::get(txid).ok_or("transaction not in the rollback window")?;
+            for out_point in &applied.created {
+                ensure!(<UtxoStore>::contains_key(out_point), "created output already spent");
+            }
+            for spent in &applied.spent {
+                ensure!(
+                    !<UtxoStore>::contains_key(spent.out_point) && !<GuardedUtxos>::contains_key(spent.out_point),
+                    "input utxo already exists"
+                );
+            }
+            let mut burned_total = <BurnedTotal>::get();
+            let mut supplies = BTreeMap::new();
+            for (asset_id, value) in applied.burned.iter() {
+                if *asset_id == NATIVE_ASSET {
+                    burned_total = burned_total.checked_sub(*value).ok_or("burned total underflow")?;
+                } else {
+                    let supply = supplies.entry(*asset_id).or_insert_with( || <AssetSupply>::get(asset_id) );
+                    *supply = supply.checked_add(*value).ok_or("asset supply overflow")?;
+                }
+            }
+            let reward_total = <RewardTotal>::get();
+            let reclaimed = applied.fee.min(reward_total);
+            let debt = <RewardDebt>::get().checked_add(applied.fee - reclaimed).ok_or("reward debt overflow")?;
+
+            <AppliedTransactions>::remove(txid);
+            <AppliedInBlock>::mutate(applied.block, |txids| txids.retain( |other| *other != txid ));
+            <RewardTotal>::put(reward_total - reclaimed);
+            Self::check_reward_high_water(reward_total - reclaimed);
+            <RewardDebt>::put(debt);
+            if applied.fee > 0 {
+                <BlockFees>::mutate(applied.block, |fees| *fees = fees.saturating_sub(applied.fee));
+            }
+            <BurnedTotal>::put(burned_total);
+            for (asset_id, supply) in supplies {
+                <AssetSupply>::insert(asset_id, supply);
+                Self::deposit_event(Event::AssetSupplyChanged(asset_id, supply));
+            }
+            for out_point in &applied.created {
+                Self::remove_utxo(out_point);
+            }
+            let now = Self::block_height();
+            for spent in applied.spent {
+                match spent.output {
+                    SpentOutput::Utxo(utxo) => Self::insert_utxo(spent.out_point, utxo),
+                    SpentOutput::Guarded(guarded) => <GuardedUtxos>::insert(spent.out_point, guarded),
+                }
+                if let Some(creator) = spent.creator {
+                    <UtxoCreator>::insert(spent.out_point, creator);
+                }
+                if let Some(minted_at) = spent.minted_at {
+                    <CoinbaseUtxos>::insert(spent.out_point, minted_at);
+                }
+                if spent.frozen {
+                    <FrozenUtxos>::insert(spent.out_point, true);
+                }
+                if applied.block == now {
+                    <SpentThisBlock>::remove(spent.out_point);
+                }
+            }
+            if applied.block == now {
+                <BlockTransactionCount>::mutate( |count| *count = count.saturating_sub(1) );
+            }
+            Self::deposit_event(Event::TransactionRolledBack(txid));
+            Ok(())
+        }
+
+        /// Check up to `limit` `UtxoStore` entries for corruption, reporting each bad one with
+        /// `IntegrityProblem` and the totals with `IntegrityScanned`. Each call resumes where the
+        /// last one stopped; once the end is reached the next call starts over.
+        #[weight = 10_000u64.saturating_mul(*limit as Weight)]
+        pub fn scan_integrity(origin, limit: u32) -> DispatchResult {
+            ensure_root(origin)?;
+            let prefix = <UtxoStore>::final_prefix();
+            let mut key = <IntegrityScanCursor>::get().unwrap_or_else( || prefix.to_vec() );
+            let mut scanned: u32 = 0;
+            let mut problems: u32 = 0;
+            while scanned < limit {
+                let next = match sp_io::storage::next_key(&key).filter( |next| next.starts_with(&prefix) ) {
+                    Some(next) => next,
+                    None => {
+                        <IntegrityScanCursor>::kill();
+                        break;
+                    },
+                };
+                // Keys are stored unhashed, so the out point follows the prefix.
+                let fault = match H256::decode(&mut &next[prefix.len()..]) {
+                    Ok(out_point) => Self::integrity_fault(&out_point).map( |fault| (out_point, fault) ),
+                    Err(_) => Some((H256::zero(), IntegrityFault::Undecodable)),
+                };
+                if let Some((out_point, fault)) = fault {
+                    problems += 1;
+                    Self::deposit_event(Event::IntegrityProblem(out_point, fault));
+                }
+                scanned += 1;
+                <IntegrityScanCursor>::put(&next);
+                key = next;
+            }
+            Self::deposit_event(Event::IntegrityScanned(scanned, problems));
+            Ok(())
+        }
+
+        /// Lock UTXOs as stake for their owners, weighting the owners' share of block rewards.
+        /// `sigs` are the owners' signatures over `(b"bond", out_point)`, one per out point.
+        #[weight = 10_000]
+        pub fn bond(_origin, out_points: Vec<H256>, sigs: Vec<H512>) -> DispatchResult {
+            ensure!(out_points.len() == sigs.len(), "each out point needs a signature");
+            {
+                let out_point_set: BTreeMap<_, ()> = out_points.iter().map( |out_point| (out_point, ()) ).collect();
+                ensure!(out_point_set.len() == out_points.len(), "each utxo must only be bonded once");
+            }
+
+            let now = Self::block_height();
+            let mut utxos = Vec::with_capacity(out_points.len());
+            for (out_point, sig) in out_points.iter().zip(sigs.iter()) {
+                let utxo = <UtxoStore>::get(out_point).ok_or("input utxo not found")?;
+                ensure!(sp_io::crypto::sr25519_verify(
+                    &Signature::from_raw(*sig.as_fixed_bytes()),
+                    &(&b"bond"[..], out_point).encode(),
+                    &Public::from_h256(utxo.pub_key)
+                ), "signature must be valid");
+                ensure!(utxo.asset_id == NATIVE_ASSET, "only native value can be bonded");
+                Self::is_spendable(out_point, &utxo, now, false)?;
+                utxos.push((*out_point, utxo));
+            }
+
+            for (out_point, utxo) in utxos {
+                Self::remove_utxo(&out_point);
+                let stake = <Stake>::get(utxo.pub_key).saturating_add(utxo.value);
+                <Stake>::insert(utxo.pub_key, stake);
+                Self::deposit_event(Event::StakeChanged(utxo.pub_key, stake));
+                <LockedUtxos>::insert(out_point, utxo);
+            }
+            Ok(())
+        }
+
+        /// Stop bonded UTXOs counting as stake. They can be withdrawn with `withdraw_unbonded`
+        /// after `UnbondingPeriod` blocks. `sigs` are the owners' signatures over
+        /// `(b"unbond", out_point)`, one per out point.
+        #[weight = 10_000]
+        pub fn unbond(_origin, out_points: Vec<H256>, sigs: Vec<H512>) -> DispatchResult {
+            ensure!(out_points.len() == sigs.len(), "each out point needs a signature");
+            {
+                let out_point_set: BTreeMap<_, ()> = out_points.iter().map( |out_point| (out_point, ()) ).collect();
+                ensure!(out_point_set.len() == out_points.len(), "each utxo must only be unbonded once");
+            }
+
+            let mut utxos = Vec::with_capacity(out_points.len());
+            for (out_point, sig) in out_points.iter().zip(sigs.iter()) {
+                let utxo = <LockedUtxos>::get(out_point).ok_or("bonded utxo not found")?;
+                ensure!(!<UnbondingUtxos>::contains_key(out_point), "utxo is already unbonding");
+                ensure!(sp_io::crypto::sr25519_verify(
+                    &Signature::from_raw(*sig.as_fixed_bytes()),
+                    &(&b"unbond"[..], out_point).encode(),
+                    &Public::from_h256(utxo.pub_key)
+                ), "signature must be valid");
+                utxos.push((*out_point, utxo));
+            }
+
+            let withdrawable_at = Self::block_height().saturating_add(T::UnbondingPeriod::get() as u64);
+            for (out_point, utxo) in utxos {
+                let stake = <Stake>::get(utxo.pub_key).saturating_sub(utxo.value);
+                <Stake>::insert(utxo.pub_key, stake);
+                Self::deposit_event(Event::StakeChanged(utxo.pub_key, stake));
+                <UnbondingUtxos>::insert(out_point, withdrawable_at);
+            }
+            Ok(())
+        }
+
+        /// Return unbonded UTXOs whose unbonding period has passed to the UTXO set, each at
+        /// `withdrawn_out_point` of its bonded out point, so the signatures that bonded and
+        /// unbonded it can't be replayed.
+        #[weight = 10_000]
+        pub fn withdraw_unbonded(_origin, out_points: Vec<H256>) -> DispatchResult {
+            {
+                let out_point_set: BTreeMap<_, ()> = out_points.iter().map( |out_point| (out_point, ()) ).collect();
+                ensure!(out_point_set.len() == out_points.len(), "each utxo must only be withdrawn once");
+            }
+
+            let now = Self::block_height();
+            let mut per_owner: BTreeMap<H256, u32> = BTreeMap::new();
+            let mut utxos = Vec::with_capacity(out_points.len());
+            for out_point in out_points.iter() {
+                let withdrawable_at = <UnbondingUtxos>::get(out_point).ok_or("utxo is not unbonding")?;
+                ensure!(now >= withdrawable_at, "unbonding period has not passed");
+                let withdrawn = Self::withdrawn_out_point(out_point);
+                ensure!(!<UtxoStore>::contains_key(withdrawn), "output already exists");
+                let utxo = <LockedUtxos>::get(out_point).ok_or("bonded utxo not found")?;
+                *per_owner.entry(utxo.pub_key).or_default() += 1;
+                utxos.push((*out_point, withdrawn, utxo));
+            }
+            for (pub_key, count) in per_owner.iter() {
+                Self::ensure_owner_capacity(pub_key, *count)?;
+            }
+
+            for (out_point, withdrawn, utxo) in utxos {
+                <UnbondingUtxos>::remove(out_point);
+                <LockedUtxos>::remove(out_point);
+                Self::insert_utxo(withdrawn, utxo);
+            }
+            Ok(())
+        }
+
+        /// Let `session_key` sign `spend` inputs owned by `owner` up to and including block
+        /// `expiry`, replacing any earlier session key. `cold_sig` is the owner's signature over
+        /// `(b"session", session_key, expiry)`. The expiry must be later than the current or last
+        /// revoked key's, so older authorizations can't be replayed.
+        #[weight = 10_000]
+        pub fn authorize_session(_origin, owner: H256, session_key: H256, expiry: u64, cold_sig: H512) -> DispatchResult {
+            ensure!(sp_io::crypto::sr25519_verify(
+                &Signature::from_raw(*cold_sig.as_fixed_bytes()),
+                &(&b"session"[..], &session_key, expiry).encode(),
+                &Public::from_h256(owner)
+            ), "signature must be valid");
+            ensure!(expiry >= Self::block_height(), "session must not expire in the past");
+            ensure!(
+                <SessionKeys>::get(owner).map_or(true, |(_, current)| expiry > current),
+                "session must expire after the current one"
+            );
+            ensure!(expiry > <RevokedSessionExpiry>::get(owner), "session must expire after the revoked one");
+
+            <SessionKeys>::insert(owner, (session_key, expiry));
+            Ok(())
+        }
+
+        /// Revoke `owner`'s session key before it expires. `cold_sig` is the owner's signature
+        /// over `(b"revoke", session_key, expiry)` of the key being revoked.
+        #[weight = 10_000]
+        pub fn revoke_session(_origin, owner: H256, cold_sig: H512) -> DispatchResult {
+            let (session_key, expiry) = <SessionKeys>::get(owner).ok_or("no session key to revoke")?;
+            ensure!(sp_io::crypto::sr25519_verify(
+                &Signature::from_raw(*cold_sig.as_fixed_bytes()),
+                &(&b"revoke"[..], &session_key, expiry).encode(),
+                &Public::from_h256(owner)
+            ), "signature must be valid");
+
+            <SessionKeys>::remove(owner);
+            <RevokedSessionExpiry>::insert(owner, expiry);
+            Ok(())
+        }
+
+        /// Hold a native UTXO for `recipient` to claim within `claim_window` blocks, refunding it
+        /// to its owner otherwise. `sig` is the owner's signature over
+        /// `(b"airdrop", out_point, recipient, claim_window)`.
+        #[weight = 10_000]
+        pub fn create_airdrop(_origin, out_point: H256, recipient: H256, claim_window: u64, sig: H512) -> DispatchResult {
+            let utxo = <UtxoStore>::get(&out_point).ok_or("input utxo not found")?;
+            ensure!(sp_io::crypto::sr25519_verify(
+                &Signature::from_raw(*sig.as_fixed_bytes()),
+                &(&b"airdrop"[..], &out_point, recipient, claim_window).encode(),
+                &Public::from_h256(utxo.pub_key)
+            ), "signature must be valid");
+            ensure!(utxo.asset_id == NATIVE_ASSET, "only native value can be airdropped");
+            Self::is_spendable(&out_point, &utxo, Self::block_height(), false)?;
+            ensure!(claim_window > 0, "claim window must not be empty");
+
+            let airdrop = Airdrop {
+                value: utxo.value,
+                recipient,
+                fallback_key: utxo.pub_key,
+                expires_at: Self::block_height().checked_add(claim_window).ok_or("claim window overflow")?,
+            };
+            let hash = BlakeTwo256::hash_of( &(&out_point, &airdrop) );
+            ensure!(! <AirdropStore>::contains_key(hash), "output already exists" );
+
+            Self::remove_utxo(&out_point);
+            <AirdropExpiries>::append(airdrop.expires_at, hash);
+            <AirdropStore>::insert(hash, airdrop);
+            Self::deposit_event(Event::AirdropCreated(hash));
+            Ok(())
+        }
+
+        /// Turn a native UTXO into a `GuardedOutput` that `guardian` must co-sign to spend. `sig`
+        /// is the owner's signature over `(b"guard", out_point, guardian)`.
+        #[weight = 10_000]
+        pub fn guard(_origin, out_point: H256, guardian: H256, sig: H512) -> DispatchResult {
+            let utxo = <UtxoStore>::get(&out_point).ok_or("input utxo not found")?;
+            ensure!(sp_io::crypto::sr25519_verify(
+                &Signature::from_raw(*sig.as_fixed_bytes()),
+                &(&b"guard"[..], &out_point, guardian).encode(),
+                &Public::from_h256(utxo.pub_key)
+            ), "signature must be valid");
+            ensure!(utxo.asset_id == NATIVE_ASSET, "only native value can be guarded");
+            Self::is_spendable(&out_point, &utxo, Self::block_height(), false)?;
+
+            let guarded = GuardedOutput { value: utxo.value, owner: utxo.pub_key, guardian };
+            let hash = BlakeTwo256::hash_of( &(&out_point, &guarded) );
+            ensure!(! <GuardedUtxos>::contains_key(hash), "output already exists" );
+
+            Self::remove_utxo(&out_point);
+            <GuardedUtxos>::insert(hash, guarded);
+            Self::deposit_event(Event::OutputGuarded(hash));
+            Ok(())
+        }
+
+        /// Claim an airdrop as its recipient. `sig` is the recipient's signature over
+        /// `(b"claim", airdrop)`.
+        #[weight = 10_000]
+        pub fn claim_airdrop(_origin, airdrop: H256, sig: H512) -> DispatchResult {
+            let held = <AirdropStore>::get(&airdrop).ok_or("airdrop not found")?;
+            ensure!(Self::block_height() < held.expires_at, "airdrop claim window has passed");
+            ensure!(sp_io::crypto::sr25519_verify(
+                &Signature::from_raw(*sig.as_fixed_bytes()),
+                &(&b"claim"[..], airdrop).encode(),
+                &Public::from_h256(held.recipient)
+            ), "signature must be valid");
+            Self::ensure_owner_capacity(&held.recipient, 1)?;
+
+            let hash = Self::release_airdrop(airdrop, held.value, held.recipient)?;
+            Self::deposit_event(Event::AirdropClaimed(airdrop, hash));
             Ok(())
         }
 
+        fn on_runtime_upgrade() -> Weight {
+            if <StorageVersion>::get() == 0 {
+                Self::migrate_to_v1();
+                <StorageVersion>::put(1);
+            }
+            T::MaximumBlockWeight::get()
+        }
+
+        fn on_initialize(n: T::BlockNumber) -> Weight {
+            <BlockTransactionCount>::kill();
+            <SpentThisBlock>::remove_all();
+            Self::prune_applied_transactions(n.saturated_into::<u64>());
+            Self::refund_expired_airdrops(n.saturated_into::<u64>());
+            0
+        }
+
         fn on_finalize() {
             let auth: Vec<_> = Aura::authorities().iter().map( |x| {
                 let r: &Public = x.as_ref();
                 r.0.into()
             }).collect();
             Self::disperse_reward(&auth);
+            Self::record_fee_rates();
+            Self::deposit_state_delta();
         }
     }
 }
 
 decl_event! {
     pub enum Event {
-        TransactionSuccess(Transaction),
+        /// A transaction was applied. [transaction, (txid, vout) of each UTXO created]
+        TransactionSuccess(Transaction, Vec<(H256, u32)>),
+        /// A UTXO was re-created with a new lock height. [old_out_point, new_out_point]
+        UtxoRefreshed(H256, H256),
+        /// Reward remainder above the cap was burned. [value]
+        RemainderBurned(Value),
+        /// An asset was minted or burned. [asset_id, new_supply]
+        AssetSupplyChanged(AssetId, Value),
+        /// UTXOs were imported from another chain. [count]
+        ExternalUtxosImported(u32),
+        /// A UTXO was turned into a hash-locked voucher. [voucher]
+        VoucherCreated(H256),
+        /// A voucher was redeemed. [voucher, out_point]
+        VoucherRedeemed(H256, H256),
+        /// A UTXO was turned into an airdrop. [airdrop]
+        AirdropCreated(H256),
+        /// An airdrop was claimed by its recipient. [airdrop, out_point]
+        AirdropClaimed(H256, H256),
+        /// An unclaimed airdrop was refunded to its fallback key. [airdrop, out_point]
+        AirdropRefunded(H256, H256),
+        /// A UTXO was turned into a guarded output. [out_point]
+        OutputGuarded(H256),
+        /// A transaction's effects were undone by root. [txid]
+        TransactionRolledBack(H256),
+        /// Idle reward dust was minted to `RewardDustKey`. [out_point, value]
+        RewardDustSwept(H256, Value),
+        /// A key was added to or removed from the burn addresses. [key, burns]
+        BurnAddressChanged(H256, bool),
+        /// A UTXO was frozen or unfrozen. [out_point, frozen]
+        UtxoFrozen(H256, bool),
+        /// A governance flag was set or cleared. [flag, value]
+        GovFlagChanged(H256, bool),
+        /// A key's bonded stake changed. [pub_key, new_stake]
+        StakeChanged(H256, Value),
+        /// `RewardTotal` rose above the high-water mark, which may mean rewards aren't being
+        /// dispersed. [reward_total]
+        RewardTotalHigh(Value),
+        /// `scan_integrity` found a corrupt `UtxoStore` entry. [out_point, fault]
+        IntegrityProblem(H256, IntegrityFault),
+        /// A `scan_integrity` call finished. [entries scanned, problems found]
+        IntegrityScanned(u32, u32),
+        /// The UTXO set's net changes in a block. [created, removed]
+        BlockStateDelta(Vec<(H256, TransactionOutput)>, Vec<H256>),
     }
 }
 
 impl<T: Trait> Module<T> {
+    fn block_height() -> u64 {
+        <frame_system::Module<T>>::block_number().saturated_into::<u64>()
+    }
+
+    /// The out point `withdraw_unbonded` returns the UTXO bonded at `bonded` to.
+    pub fn withdrawn_out_point(bonded: &H256) -> H256 {
+        BlakeTwo256::hash_of( &(&b"withdraw"[..], bonded) )
+    }
+
+    fn stored() -> StoredState<T> {
+        StoredState(sp_std::marker::PhantomData)
+    }
+
+    /// Panics unless every non-native asset in `utxos` is declared in `supplies`, and the
+    /// genesis outputs of every declared asset add up to its declared supply.
+    fn check_genesis_asset_supplies(utxos: &[TransactionOutput], supplies: &BTreeMap<AssetId, Value>) {
+        let mut totals: BTreeMap<AssetId, Value> = BTreeMap::new();
+        for utxo in utxos {
+            let total = totals.entry(utxo.asset_id).or_default();
+            *total = total.checked_add(utxo.value).expect("genesis asset supply overflow");
+        }
+        for asset_id in totals.keys() {
+            assert!(
+                *asset_id == NATIVE_ASSET || supplies.contains_key(asset_id),
+                "genesis asset {} has no declared supply", asset_id
+            );
+        }
+        for (asset_id, supply) in supplies.iter() {
+            let total = totals.get(asset_id).cloned().unwrap_or_default();
+            assert!(
+                total == *supply,
+                "genesis outputs of asset {} total {} but declared supply is {}", asset_id, total, supply
+            );
+        }
+    }
+
+    /// Panics unless the native outputs in `utxos` and `initial_reward` add up to `supply`.
+    fn check_genesis_native_supply(utxos: &[TransactionOutput], initial_reward: Value, supply: Value) {
+        let total = utxos.iter()
+            .filter( |utxo| utxo.asset_id == NATIVE_ASSET )
+            .try_fold(initial_reward, |total, utxo| total.checked_add(utxo.value))
+            .expect("genesis native supply overflow");
+        assert!(
+            total == supply,
+            "genesis native outputs and initial reward total {} but declared supply is {}", total, supply
+        );
+    }
+
+    /// The identifier of `transaction`; its outputs are numbered from zero within it.
+    pub fn txid(transaction: &Transaction) -> H256 {
+        BlakeTwo256::hash_of(transaction)
+    }
+
     pub fn get_simple_transaction (transaction: &Transaction) -> Vec<u8> {
         let mut trx = transaction.clone();
         for input in trx.inputs.iter_mut() {
             input.sig_script = H512::zero();
+            input.guardian_sig = None;
         }
         trx.encode()
     }
 
+    /// Validate and apply `transaction` for `spend`, or for `force_spend` when `ignore_locks`.
+    fn apply_transaction(transaction: Transaction, ignore_locks: bool) -> DispatchResult {
+        let count = <BlockTransactionCount>::get();
+        ensure!(count < T::MaxTransactionsPerBlock::get(), "block transaction limit reached");
+        ensure!(
+            !transaction.inputs.iter().any( |input| <SpentThisBlock>::get(input.out_point) ),
+            "input already spent this block"
+        );
+        Self::check_transaction(&Self::stored(), &transaction, &Self::stored_witnesses(&transaction), ignore_locks)?;
+        let fee = Self::transaction_fee(&transaction)?;
+        let spent_utxos: Vec<_> = transaction.inputs.iter()
+            .filter_map( |input| Self::spent_utxo(&input.out_point) )
+            .collect();
+        let burned: Vec<_> = transaction.outputs.iter()
+            .filter( |output| Self::is_burn_address(&output.pub_key) )
+            .map( |output| (output.asset_id, output.value) )
+            .collect();
+        Self::update_storage(&transaction)?;
+        <BlockTransactionCount>::put(count + 1);
+        for input in &transaction.inputs {
+            <SpentThisBlock>::insert(input.out_point, true);
+        }
+        let txid = Self::txid(&transaction);
+        let encoded = transaction.encode();
+        let vouts: Vec<_> = transaction.outputs.iter()
+            .enumerate()
+            .filter( |(_, output)| Self::creates_utxo(output) )
+            .map( |(vout, _)| vout as u32 )
+            .collect();
+        let spent: Vec<_> = transaction.inputs.iter().map( |input| input.out_point ).collect();
+        let created: Vec<_> = vouts.iter()
+            .map( |vout| BlakeTwo256::hash_of( &(&encoded, *vout as u64) ) )
+            .collect();
+        if T::RollbackWindow::get() > 0 {
+            let block = Self::block_height();
+            <AppliedTransactions>::insert(txid, AppliedTransaction {
+                block,
+                spent: spent_utxos,
+                created: created.clone(),
+                fee,
+                burned,
+            });
+            <AppliedInBlock>::append(block, txid);
+        }
+        T::PostSpend::on_applied(txid, &spent, &created);
+        Self::deposit_event(Event::TransactionSuccess(
+            transaction,
+            vouts.into_iter().map( |vout| (txid, vout) ).collect(),
+        ));
+        Ok(())
+    }
+
     pub fn validate_transaction(transaction: &Transaction) -> Result<ValidTransaction, &'static str> {
+        Self::validate_transaction_with_witness(transaction, &Self::stored_witnesses(transaction))
+    }
+
+    /// The stored UTXOs `transaction` spends.
+    fn stored_witnesses(transaction: &Transaction) -> BTreeMap<H256, TransactionOutput> {
+        transaction.inputs.iter()
+            .filter_map( |input| {
+                <UtxoStore>::get(&input.out_point).map( |utxo| (input.out_point, utxo) )
+            })
+            .collect()
+    }
+
+    /// Validate `transaction` taking the outputs it spends from `witnesses` rather than
+    /// `UtxoStore`, e.g. for a client holding UTXO set proofs. Inputs without a witness are
+    /// treated as not created yet, unless they're guarded outputs. Other state, such as guarded
+    /// outputs, governance flags, session keys and balances, is still read from the chain.
+    pub fn validate_transaction_with_witness(
+        transaction: &Transaction,
+        witnesses: &BTreeMap<H256, TransactionOutput>,
+    ) -> Result<ValidTransaction, &'static str> {
+        Self::check_transaction(&Self::stored(), transaction, witnesses, false)
+    }
+
+    /// `validate_transaction_with_witness` against `state` rather than the chain, skipping the
+    /// optional locks on inputs if `ignore_locks`.
+    fn check_transaction<S: UtxoState>(
+        state: &S,
+        transaction: &Transaction,
+        witnesses: &BTreeMap<H256, TransactionOutput>,
+        ignore_locks: bool,
+    ) -> Result<ValidTransaction, &'static str> {
+        T::PreFilter::allow(transaction)?;
         ensure!(!transaction.inputs.is_empty(), "no inputs");
         ensure!(!transaction.outputs.is_empty(), "no outputs");
+        ensure!(
+            transaction.outputs.iter().filter( |output| state.creates_utxo(output) ).count()
+                >= T::MinOutputs::get() as usize,
+            "too few outputs"
+        );
 
         {
             let input_set: BTreeMap<_, ()> = transaction.inputs.iter().map( |input| (input, ()) ).collect();
@@ -114,98 +2030,1122 @@ impl<T: Trait> Module<T> {
         let simple_transaction = Self::get_simple_transaction(transaction);
         let mut total_input: Value = 0;
         let mut total_output: Value = 0;
+        let mut explicit_fee: Value = 0;
+        // Non-native assets can't pay fees, so their inputs and outputs must balance exactly.
+        let mut asset_totals: BTreeMap<AssetId, (Value, Value)> = BTreeMap::new();
+        // Native value each key spends and receives.
+        let mut key_flows: BTreeMap<H256, (Value, Value)> = BTreeMap::new();
+        // UTXOs each key spends and receives.
+        let mut key_counts: BTreeMap<H256, (u32, u32)> = BTreeMap::new();
 
         let mut missing_utxos = Vec::new();
         let mut new_utxos = Vec::new();
         let mut reward = 0;
 
+        let current_height = state.block_height();
+        let consolidation = Self::is_consolidation(state, transaction);
+        let threshold = T::BatchVerifyThreshold::get() as usize;
+        let batch = threshold > 0 && transaction.inputs.len() >= threshold;
+        let mut batched = Vec::new();
+
         for input in transaction.inputs.iter() {
-            if let Some(input_utxo) = <UtxoStore>::get(&input.out_point) {
+            if let Some(input_utxo) = witnesses.get(&input.out_point) {
+                Self::check_spendable(
+                    state, &input.out_point, input_utxo, current_height, consolidation, ignore_locks
+                )?;
+                if batch {
+                    batched.push((input.sig_script, input_utxo.pub_key));
+                } else {
+                    ensure!(
+                        Self::signed_by_owner(
+                            state, &input.sig_script, &simple_transaction, &input_utxo.pub_key, current_height
+                        ),
+                        "signature must be valid"
+                    );
+                }
+                key_counts.entry(input_utxo.pub_key).or_default().0 += 1;
+                if input_utxo.asset_id == NATIVE_ASSET {
+                    total_input = total_input.checked_add(input_utxo.value).ok_or("input value overflow")?;
+                    let flow = key_flows.entry(input_utxo.pub_key).or_default();
+                    flow.0 = flow.0.checked_add(input_utxo.value).ok_or("input value overflow")?;
+                } else {
+                    let totals = asset_totals.entry(input_utxo.asset_id).or_default();
+                    totals.0 = totals.0.checked_add(input_utxo.value).ok_or("input value overflow")?;
+                }
+            } else if let Some(guarded) = state.guarded_output(&input.out_point) {
+                ensure!(
+                    Self::signed_by_owner(
+                        state, &input.sig_script, &simple_transaction, &guarded.owner, current_height
+                    ),
+                    "signature must be valid"
+                );
+                let guardian_sig = input.guardian_sig.ok_or("guardian signature missing")?;
                 ensure!(sp_io::crypto::sr25519_verify(
-                    &Signature::from_raw(*input.sig_script.as_fixed_bytes()),
+                    &Signature::from_raw(*guardian_sig.as_fixed_bytes()),
                     &simple_transaction,
-                    &Public::from_h256(input_utxo.pub_key)
-                ), "signature must be valid");
-                total_input = total_input.checked_add(input_utxo.value).ok_or("input value overflow")?;
+                    &Public::from_h256(guarded.guardian)
+                ), "guardian signature must be valid");
+                total_input = total_input.checked_add(guarded.value).ok_or("input value overflow")?;
             } else {
                 missing_utxos.push(input.out_point.clone().as_fixed_bytes().to_vec());
             }
         }
+        ensure!(
+            !batch || Self::all_signed_by_owners(state, &simple_transaction, &batched, current_height),
+            "input signatures must be valid"
+        );
 
         let mut output_index: u64 = 0;
 
         for output in transaction.outputs.iter() {
             ensure!(output.value > 0, "output value must be nonzero");
+            ensure!(
+                !state.creates_utxo(output) || output.value >= T::DustThreshold::get(),
+                "output value below dust threshold"
+            );
+            ensure!(
+                output.pub_key != FEE_ADDRESS || output.asset_id == NATIVE_ASSET,
+                "fees must be paid in the native asset"
+            );
+            ensure!(output.metadata.len() <= T::MaxMetadataLength::get() as usize, "output metadata too long");
+            ensure!(output.memo.len() <= T::MaxMemoLength::get() as usize, "output memo too long");
             let hash = BlakeTwo256::hash_of( &(&transaction.encode(), output_index) );
             output_index = output_index.checked_add(1).ok_or("output index overflow")?;
-            ensure!(! <UtxoStore>::contains_key(hash), "output already exists" );
-            total_output = total_output.checked_add(output.value).ok_or("output value overflow")?;
+            ensure!(!state.contains_utxo(&hash), "output already exists" );
+            if state.creates_utxo(output) {
+                key_counts.entry(output.pub_key).or_default().1 += 1;
+            }
+            if output.asset_id == NATIVE_ASSET {
+                total_output = total_output.checked_add(output.value).ok_or("output value overflow")?;
+                if state.creates_utxo(output) {
+                    let flow = key_flows.entry(output.pub_key).or_default();
+                    flow.1 = flow.1.checked_add(output.value).ok_or("output value overflow")?;
+                }
+            } else {
+                let totals = asset_totals.entry(output.asset_id).or_default();
+                totals.1 = totals.1.checked_add(output.value).ok_or("output value overflow")?;
+            }
+            if output.pub_key == FEE_ADDRESS {
+                explicit_fee = explicit_fee.checked_add(output.value).ok_or("output value overflow")?;
+            }
             new_utxos.push(hash.as_fixed_bytes().to_vec());
         }
 
+        // Every native input or output carries value, so the totals show whether native is used.
+        let native_assets = if total_input > 0 || total_output > 0 { 1 } else { 0 };
+        ensure!(
+            asset_totals.len() + native_assets <= T::MaxAssetsPerTransaction::get() as usize,
+            "too many assets in transaction"
+        );
+
         if missing_utxos.is_empty() {
             ensure!( total_input >= total_output, "output value must not excceed input value" );
-            reward = total_input.checked_sub(total_output).ok_or("reward underflow")?;
+            ensure!(
+                asset_totals.values().all( |(input, output)| input == output ),
+                "asset input and output values must match"
+            );
+            if T::StrictBalance::get() {
+                ensure!(total_input == total_output, "input value must equal output value");
+            }
+            // Keys already above the cap may still spend, as long as they don't gain value.
+            for (key, (spent, received)) in key_flows.iter() {
+                if received > spent {
+                    let balance = state.balance_of(key).saturating_sub(*spent).saturating_add(*received);
+                    ensure!(balance <= T::MaxBalancePerKey::get(), "recipient balance would exceed cap");
+                }
+            }
+            for (key, (spent, received)) in key_counts.iter() {
+                if received > spent {
+                    Self::ensure_capacity_in(state, key, received - spent)?;
+                }
+            }
+            let implicit_fee = total_input.checked_sub(total_output).ok_or("reward underflow")?;
+            ensure!(implicit_fee <= T::MaxFee::get(), "fee exceeds sanity limit");
+            reward = implicit_fee.checked_add(explicit_fee).ok_or("reward overflow")?;
+            ensure!(reward >= Self::minimum_fee_in(state, transaction), "fee below minimum");
         }
 
         Ok(ValidTransaction{
             requires: missing_utxos,
             provides: new_utxos,
-            priority: reward as u64,
+            priority: Self::pool_priority(
+                Self::priority_for_fee(reward, transaction.encode().len() as u32),
+                transaction.priority_hint,
+            ),
             longevity: TransactionLongevity::max_value(),
             propagate: true,
         })
     }
 
-    fn update_storage(transaction: &Transaction, reward: Value) -> DispatchResult {
+    /// Replace an airdrop with a UTXO of `value` owned by `pub_key`.
+    fn release_airdrop(airdrop: H256, value: Value, pub_key: H256) -> Result<H256, &'static str> {
+        let utxo = TransactionOutput {
+            value,
+            pub_key,
+            lock_height: 0,
+            asset_id: NATIVE_ASSET,
+            metadata: Vec::new(),
+            memo: Vec::new(),
+            requires_gov_flag: None,
+        };
+        let hash = BlakeTwo256::hash_of( &(&airdrop, &pub_key) );
+        ensure!(! <UtxoStore>::contains_key(hash), "output already exists" );
+
+        <AirdropStore>::remove(airdrop);
+        Self::insert_utxo(hash, utxo);
+        Ok(hash)
+    }
+
+    /// Refund expired airdrops oldest first, within `MaxSweepPerBlock` steps.
+    ///
+    /// Refunds always go ahead, even if they take the fallback key past `MaxUtxosPerOwner`.
+    fn refund_expired_airdrops(now: u64) {
+        let (mut height, mut done) = <AirdropSweepCursor>::get().unwrap_or((now, 0));
+        let mut steps = T::MaxSweepPerBlock::get() as usize;
+        while height <= now && steps > 0 {
+            let expiring = <AirdropExpiries>::get(height);
+            let start = (done as usize).min(expiring.len());
+            let end = expiring.len().min(start + steps);
+            for airdrop in &expiring[start..end] {
+                // Claimed airdrops are already gone.
+                if let Some(held) = <AirdropStore>::get(airdrop) {
+                    match Self::release_airdrop(*airdrop, held.value, held.fallback_key) {
+                        Ok(hash) => Self::deposit_event(Event::AirdropRefunded(*airdrop, hash)),
+                        Err(e) => sp_runtime::print(e),
+                    }
+                }
+            }
+            steps -= end - start;
+            done = end as u32;
+            if end == expiring.len() && steps > 0 {
+                <AirdropExpiries>::remove(height);
+                height += 1;
+                done = 0;
+                steps -= 1;
+            }
+        }
+        <AirdropSweepCursor>::put((height, done));
+    }
+
+    /// The smallest fee `transaction` may pay, see `required_fee`.
+    pub fn minimum_fee(transaction: &Transaction) -> Value {
+        Self::minimum_fee_in(&Self::stored(), transaction)
+    }
+
+    fn minimum_fee_in<S: UtxoState>(state: &S, transaction: &Transaction) -> Value {
+        let created = transaction.outputs.iter().filter( |output| state.creates_utxo(output) ).count();
+        Self::required_fee(created as i64 - transaction.inputs.len() as i64)
+    }
+
+    /// The smallest fee a transaction adding `net_new_outputs` UTXOs to the set may pay:
+    /// `MinimumFee` plus `FeePerNewOutput` for each, or `MinimumFee` less the consolidation
+    /// discount if it shrinks the set.
+    pub fn required_fee(net_new_outputs: i64) -> Value {
+        let floor = T::MinimumFee::get();
+        if net_new_outputs < 0 {
+            floor - T::ConsolidationDiscount::get() * floor
+        } else {
+            floor.saturating_add(T::FeePerNewOutput::get().saturating_mul(net_new_outputs as Value))
+        }
+    }
+
+    /// Checks the UTXO at `out_point` can be spent at `now`, by a consolidation or not, leaving
+    /// the signature to the caller. When several reasons apply the first of these is reported:
+    ///
+    /// 1. "input utxo is frozen"
+    /// 2. "can't spend from a burn address"
+    /// 3. "input utxo awaits a governance flag"
+    /// 4. "input utxo is time-locked"
+    /// 5. "immature coinbase"
+    /// 6. "reward utxo within reorg window"
+    /// 7. "input value below minimum, consolidate it first"
+    ///
+    /// Blocks only governance can lift come first, then ones that pass with time, then the one
+    /// the owner can fix.
+    pub fn is_spendable(
+        out_point: &H256,
+        utxo: &TransactionOutput,
+        now: u64,
+        consolidation: bool,
+    ) -> Result<(), &'static str> {
+        Self::check_spendable(&Self::stored(), out_point, utxo, now, consolidation, false)
+    }
+
+    /// `is_spendable` against `state`, skipping the freeze, governance flag and time-lock if
+    /// `ignore_locks`.
+    fn check_spendable<S: UtxoState>(
+        state: &S,
+        out_point: &H256,
+        utxo: &TransactionOutput,
+        now: u64,
+        consolidation: bool,
+        ignore_locks: bool,
+    ) -> Result<(), &'static str> {
+        ensure!(ignore_locks || !state.is_frozen(out_point), "input utxo is frozen");
+        ensure!(!state.is_burn_address(&utxo.pub_key), "can't spend from a burn address");
+        ensure!(
+            ignore_locks || utxo.requires_gov_flag.map_or(true, |flag| state.gov_flag(&flag)),
+            "input utxo awaits a governance flag"
+        );
+        ensure!(ignore_locks || utxo.lock_height <= now, "input utxo is time-locked");
+        ensure!(Self::is_mature(state, out_point, now), "immature coinbase");
+        ensure!(Self::outside_reorg_window(state, out_point, now), "reward utxo within reorg window");
+        ensure!(
+            consolidation || utxo.value >= T::MinInputValue::get(),
+            "input value below minimum, consolidate it first"
+        );
+        Ok(())
+    }
+
+    /// Whether `sig` is `owner`'s signature over `message`, or that of a session key `owner` has
+    /// authorized until at least `now`.
+    fn signed_by_owner<S: UtxoState>(state: &S, sig: &H512, message: &[u8], owner: &H256, now: u64) -> bool {
+        let signed_by = |key: &H256| sp_io::crypto::sr25519_verify(
+            &Signature::from_raw(*sig.as_fixed_bytes()),
+            message,
+            &Public::from_h256(*key)
+        );
+        signed_by(owner) || state.session_key(owner)
+            .map_or(false, |(session_key, expiry)| now <= expiry && signed_by(&session_key))
+    }
+
+    /// Whether each `(sig, owner)` in `signed` passes `signed_by_owner`, checking all of them.
+    /// They aren't verified with `start_batch_verify`: block import already holds a batch open,
+    /// and starting another panics.
+    fn all_signed_by_owners<S: UtxoState>(state: &S, message: &[u8], signed: &[(H512, H256)], now: u64) -> bool {
+        signed.iter()
+            .fold(true, |valid, (sig, owner)| Self::signed_by_owner(state, sig, message, owner, now) && valid)
+    }
+
+    /// Whether `transaction` creates fewer UTXOs than it spends.
+    fn is_consolidation<S: UtxoState>(state: &S, transaction: &Transaction) -> bool {
+        let created = transaction.outputs.iter().filter( |output| state.creates_utxo(output) ).count();
+        created < transaction.inputs.len()
+    }
+
+    /// Transaction pool priority for paying `fee` on a transaction of `tx_size` encoded bytes:
+    /// the fee per thousand bytes.
+    pub fn priority_for_fee(fee: Value, tx_size: u32) -> u64 {
+        (fee.saturating_mul(1000) / Value::from(tx_size.max(1))).saturated_into::<u64>()
+    }
+
+    /// Transaction pool priority for a transaction paying `fee_rate`, as from `priority_for_fee`.
+    /// `priority_hint` only breaks ties between transactions paying the same rate.
+    pub fn pool_priority(fee_rate: u64, priority_hint: u8) -> u64 {
+        fee_rate.saturating_mul(256).saturating_add(priority_hint.into())
+    }
+
+    /// The smallest fee for which a transaction of `tx_size` encoded bytes without a priority
+    /// hint reaches a `pool_priority` of `target_priority`.
+    pub fn fee_for_priority(target_priority: u64, tx_size: u32) -> Value {
+        let fee_rate = Value::from(target_priority).saturating_add(255) / 256;
+        fee_rate
+            .saturating_mul(Value::from(tx_size.max(1)))
+            .saturating_add(999) / 1000
+    }
+
+    /// The fee rate `transaction` pays, in thousandths of a fee unit per encoded byte. Its
+    /// transaction pool priority is `pool_priority` of this rate and its `priority_hint`.
+    pub fn fee_rate(transaction: &Transaction) -> Result<u64, &'static str> {
+        let fee = Self::transaction_fee(transaction)?;
+        Ok(Self::priority_for_fee(fee, transaction.encode().len() as u32))
+    }
+
+    /// Build an unsigned transaction paying `value` from `from`'s UTXOs to `to`, leaving `fee`
+    /// to the block authors. Change below the dust threshold is added to the fee rather than
+    /// returned to `from`. With `ConsolidateChange`, change is topped up with `from`'s smallest
+    /// unused UTXO that isn't below `MinInputValue`.
+    pub fn send(from: H256, to: H256, value: Value, fee: Value) -> Result<Transaction, &'static str> {
+        let needed = value.checked_add(fee).ok_or("value overflow")?;
+        let now = Self::block_height();
+        let mut candidates: Vec<_> = Self::utxos_of(from)
+            .into_iter()
+            .filter( |(out_point, utxo)| utxo.asset_id == NATIVE_ASSET
+                && Self::is_spendable(out_point, utxo, now, false).is_ok() )
+            .collect();
+        // Largest first, so as few inputs as possible are used.
+        candidates.sort_by( |a, b| b.1.value.cmp(&a.1.value) );
+
+        let mut unused = candidates.into_iter();
+        let mut inputs = Vec::new();
+        let mut total: Value = 0;
+        while total < needed {
+            let (out_point, utxo) = unused.next().ok_or("insufficient funds")?;
+            inputs.push(out_point);
+            total = total.checked_add(utxo.value).ok_or("input value overflow")?;
+        }
+        let change = total - needed;
+        if T::ConsolidateChange::get() && change > 0 && change >= T::DustThreshold::get() {
+            let min_input = T::MinInputValue::get();
+            if let Some((out_point, utxo)) = unused.filter( |(_, utxo)| utxo.value >= min_input ).last() {
+                inputs.push(out_point);
+                total = total.checked_add(utxo.value).ok_or("input value overflow")?;
+            }
+        }
+        Self::build_payment(inputs, total, from, to, value, fee)
+    }
+
+    /// Build an unsigned transaction paying `value` out of the single UTXO at `out_point` to
+    /// `to`, leaving `fee` to the block authors. Change below the dust threshold is added to the
+    /// fee rather than returned to the owner.
+    pub fn partial_spend(out_point: H256, to: H256, value: Value, fee: Value) -> Result<Transaction, &'static str> {
+        let utxo = <UtxoStore>::get(&out_point).ok_or("input utxo not found")?;
+        ensure!(utxo.asset_id == NATIVE_ASSET, "only native value can be sent");
+        ensure!(utxo.value >= value.checked_add(fee).ok_or("value overflow")?, "insufficient funds");
+        Self::build_payment(vec![out_point], utxo.value, utxo.pub_key, to, value, fee)
+    }
+
+    fn build_payment(
+        inputs: Vec<H256>,
+        total_input: Value,
+        owner: H256,
+        to: H256,
+        value: Value,
+        fee: Value,
+    ) -> Result<Transaction, &'static str> {
+        let output = |value, pub_key| TransactionOutput {
+            value,
+            pub_key,
+            lock_height: 0,
+            asset_id: NATIVE_ASSET,
+            metadata: Vec::new(),
+            memo: Vec::new(),
+            requires_gov_flag: None,
+        };
+        let mut outputs = vec![output(value, to)];
+        let change = total_input - value - fee;
+        if change > 0 && change >= T::DustThreshold::get() {
+            outputs.push(output(change, owner));
+        }
+        Ok(Transaction {
+            inputs: inputs.into_iter()
+                .map( |out_point| TransactionInput { out_point, sig_script: H512::zero(), guardian_sig: None })
+                .collect(),
+            outputs,
+            nonce: 0,
+            priority_hint: 0,
+        })
+    }
+
+    /// The median of recent blocks' median fee rates, or zero without any history.
+    pub fn suggested_fee_rate() -> u64 {
+        Self::median(<RecentFeeRates>::get()).unwrap_or(0)
+    }
+
+    /// Fold the current block's fee rates into `RecentFeeRates`.
+    fn record_fee_rates() {
+        if let Some(median) = Self::median(<BlockFeeRates>::take()) {
+            let mut recent = <RecentFeeRates>::get();
+            recent.push(median);
+            let window = T::FeeRateWindow::get() as usize;
+            if recent.len() > window {
+                recent.drain(..recent.len() - window);
+            }
+            <RecentFeeRates>::put(recent);
+        }
+    }
+
+    fn median(mut rates: Vec<u64>) -> Option<u64> {
+        if rates.is_empty() {
+            return None;
+        }
+        rates.sort();
+        let middle = rates.len() / 2;
+        if rates.len() % 2 == 0 {
+            Some(rates[middle - 1] / 2 + rates[middle] / 2 + (rates[middle - 1] % 2 + rates[middle] % 2) / 2)
+        } else {
+            Some(rates[middle])
+        }
+    }
+
+    /// Add a UTXO, crediting its owner's balance and tracking it in `OwnerUtxos`.
+    fn insert_utxo(out_point: H256, utxo: TransactionOutput) {
+        if utxo.asset_id == NATIVE_ASSET {
+            <BalanceIndex>::mutate(utxo.pub_key, |balance| *balance = balance.saturating_add(utxo.value));
+        }
+        <OwnerUtxos>::append(utxo.pub_key, out_point);
+        if T::EmitStateDelta::get() {
+            <BlockStateChanges>::mutate(out_point, |change| {
+                let existed = change.as_ref().map_or(false, |(existed, _)| *existed);
+                *change = Some((existed, Some(utxo.clone())));
+            });
+        }
+        <UtxoStore>::insert(out_point, utxo);
+    }
+
+    /// Ensure `pub_key` can be given `additional` more UTXOs within `MaxUtxosPerOwner`.
+    fn ensure_owner_capacity(pub_key: &H256, additional: u32) -> DispatchResult {
+        Self::ensure_capacity_in(&Self::stored(), pub_key, additional)
+    }
+
+    fn ensure_capacity_in<S: UtxoState>(state: &S, pub_key: &H256, additional: u32) -> DispatchResult {
+        ensure!(
+            state.utxo_count(pub_key).saturating_add(additional) <= T::MaxUtxosPerOwner::get(),
+            "owner has too many utxos"
+        );
+        Ok(())
+    }
+
+    /// The total native value of `pub_key`'s UTXOs, summed from the UTXOs themselves. It only
+    /// reads the state it runs against, so it gives historical balances when run against a past
+    /// block's state.
+    pub fn owned_value(pub_key: H256) -> Value {
+        Self::utxos_of(pub_key)
+            .into_iter()
+            .filter( |(_, utxo)| utxo.asset_id == NATIVE_ASSET )
+            .fold(0, |total: Value, (_, utxo)| total.saturating_add(utxo.value))
+    }
+
+    /// The total native balance of `keys`, each key counted once.
+    pub fn proof_of_reserves(keys: &[H256]) -> Value {
+        let keys: BTreeMap<_, ()> = keys.iter().map( |key| (key, ()) ).collect();
+        keys.keys().fold(0, |total: Value, key| total.saturating_add(<BalanceIndex>::get(key)))
+    }
+
+    /// The reserves of the keys in `attestations`, each of which must come with its owner's
+    /// signature over `(b"reserves", challenge)`, proving control of the key.
+    pub fn attested_reserves(challenge: &[u8], attestations: &[(H256, H512)]) -> Result<Value, &'static str> {
+        let message = (&b"reserves"[..], challenge).encode();
+        for (key, sig) in attestations {
+            ensure!(sp_io::crypto::sr25519_verify(
+                &Signature::from_raw(*sig.as_fixed_bytes()),
+                &message,
+                &Public::from_h256(*key)
+            ), "signature must be valid");
+        }
+        let keys: Vec<_> = attestations.iter().map( |(key, _)| *key ).collect();
+        Ok(Self::proof_of_reserves(&keys))
+    }
+
+    /// The out points and outputs of `pub_key`'s UTXOs.
+    pub fn utxos_of(pub_key: H256) -> Vec<(H256, TransactionOutput)> {
+        <OwnerUtxos>::get(pub_key)
+            .into_iter()
+            .filter_map( |out_point| <UtxoStore>::get(out_point).map( |utxo| (out_point, utxo) ) )
+            .collect()
+    }
+
+    /// The out point and value of `pub_key`'s largest native UTXO that it can spend now.
+    pub fn largest_utxo(pub_key: H256) -> Option<(H256, Value)> {
+        let now = Self::block_height();
+        Self::utxos_of(pub_key)
+            .into_iter()
+            .filter( |(out_point, utxo)| {
+                utxo.asset_id == NATIVE_ASSET && Self::is_spendable(out_point, utxo, now, true).is_ok()
+            })
+            .max_by_key( |(_, utxo)| utxo.value )
+            .map( |(out_point, utxo)| (out_point, utxo.value) )
+    }
+
+    /// Whether each of `out_points` is a stored UTXO that can be spent now, ignoring
+    /// `MinInputValue` since the transaction might be a consolidation. Pending spends in the
+    /// transaction pool aren't known here.
+    pub fn inputs_available(out_points: &[H256]) -> Vec<bool> {
+        let now = Self::block_height();
+        out_points.iter()
+            .map( |out_point| {
+                <UtxoStore>::get(out_point)
+                    .map_or(false, |utxo| Self::is_spendable(out_point, &utxo, now, true).is_ok())
+            })
+            .collect()
+    }
+
+    /// The first thing wrong with the `UtxoStore` entry at `out_point`, if any.
+    fn integrity_fault(out_point: &H256) -> Option<IntegrityFault> {
+        let utxo = match <UtxoStore>::get(out_point) {
+            Some(utxo) => utxo,
+            None => return Some(IntegrityFault::Undecodable),
+        };
+        if utxo.value == 0 {
+            Some(IntegrityFault::ZeroValue)
+        } else if !Self::creates_utxo(&utxo) {
+            Some(IntegrityFault::UnownableKey)
+        } else if !<OwnerUtxos>::get(utxo.pub_key).contains(out_point) {
+            Some(IntegrityFault::NotIndexed)
+        } else {
+            None
+        }
+    }
+
+    /// Remove a UTXO along with everything recorded about it.
+    fn remove_utxo(out_point: &H256) {
+        if let Some(utxo) = <UtxoStore>::take(out_point) {
+            if T::EmitStateDelta::get() {
+                <BlockStateChanges>::mutate(out_point, |change| {
+                    let existed = change.as_ref().map_or(true, |(existed, _)| *existed);
+                    *change = Some((existed, None));
+                });
+            }
+            if utxo.asset_id == NATIVE_ASSET {
+                <BalanceIndex>::mutate_exists(utxo.pub_key, |balance| {
+                    *balance = balance.map( |b| b.saturating_sub(utxo.value) ).filter( |b| *b > 0 );
+                });
+            }
+            <OwnerUtxos>::mutate_exists(utxo.pub_key, |owned| {
+                let emptied = owned.as_mut().map_or(false, |list| {
+                    list.retain( |tracked| tracked != out_point );
+                    list.is_empty()
+                });
+                if emptied {
+                    *owned = None;
+                }
+            });
+        }
+        <UtxoCreator>::remove(out_point);
+        <CoinbaseUtxos>::remove(out_point);
+        <FrozenUtxos>::remove(out_point);
+    }
+
+    /// Whether the UTXO at `out_point` was minted as a block reward.
+    pub fn is_coinbase(out_point: &H256) -> bool {
+        <CoinbaseUtxos>::contains_key(out_point)
+    }
+
+    /// Whether the UTXO at `out_point` is not a reward, or is a reward that has matured.
+    fn is_mature<S: UtxoState>(state: &S, out_point: &H256, now: u64) -> bool {
+        state.minted_at(out_point)
+            .map_or(true, |minted_at| now >= minted_at.saturating_add(T::CoinbaseMaturity::get() as u64))
+    }
+
+    /// Whether the UTXO at `out_point` is not a reward, or is a reward minted at least
+    /// `RewardReorgWindow` blocks before `now`.
+    fn outside_reorg_window<S: UtxoState>(state: &S, out_point: &H256, now: u64) -> bool {
+        state.minted_at(out_point)
+            .map_or(true, |minted_at| now >= minted_at.saturating_add(T::RewardReorgWindow::get() as u64))
+    }
+
+    /// Whether `output` is added to the UTXO set, rather than being burned or paid as a fee.
+    fn creates_utxo(output: &TransactionOutput) -> bool {
+        Self::stored().creates_utxo(output)
+    }
+
+    /// The fees collected in blocks `from` to `to` inclusive. Fails if part of the range is older
+    /// than the reward history or hasn't been built yet.
+    pub fn fees_collected(from: u64, to: u64) -> Result<Value, &'static str> {
+        let now = Self::block_height();
+        ensure!(from <= to, "empty block range");
+        ensure!(to <= now, "block range ends in the future");
+        let oldest = now.checked_sub(T::RewardHistoryRetention::get() as u64).map_or(0, |expired| expired + 1);
+        ensure!(from >= oldest, "block range has been pruned");
+        Ok((from..=to).fold(0, |total: Value, block| total.saturating_add(<BlockFees>::get(block))))
+    }
+
+    /// Whether outputs to `key` are burned.
+    pub fn is_burn_address(key: &H256) -> bool {
+        Self::stored().is_burn_address(key)
+    }
+
+    /// The native value `transaction` leaves to the block authors, both implicitly and through
+    /// outputs to `FEE_ADDRESS`. Every input must exist.
+    fn transaction_fee(transaction: &Transaction) -> Result<Value, &'static str> {
+        let mut total_input: Value = 0;
+        for input in &transaction.inputs {
+            let input_utxo = Self::spent_output(&input.out_point).ok_or("input utxo not found")?;
+            if input_utxo.asset_id == NATIVE_ASSET {
+                total_input = total_input.checked_add(input_utxo.value).ok_or("input value overflow")?;
+            }
+        }
+        let mut total_output: Value = 0;
+        let paid_out = transaction.outputs.iter()
+            .filter( |output| output.asset_id == NATIVE_ASSET && output.pub_key != FEE_ADDRESS );
+        for output in paid_out {
+            total_output = total_output.checked_add(output.value).ok_or("output value overflow")?;
+        }
+        total_input.checked_sub(total_output).ok_or("output value must not excceed input value")
+    }
+
+    /// The UTXO or guarded output at `out_point` and its records, as `rollback_transaction`
+    /// restores them.
+    fn spent_utxo(out_point: &H256) -> Option<SpentUtxo> {
+        let output = <UtxoStore>::get(out_point).map(SpentOutput::Utxo)
+            .or_else( || <GuardedUtxos>::get(out_point).map(SpentOutput::Guarded) )?;
+        Some(SpentUtxo {
+            out_point: *out_point,
+            output,
+            creator: <UtxoCreator>::get(out_point),
+            minted_at: <CoinbaseUtxos>::get(out_point),
+            frozen: <FrozenUtxos>::get(out_point),
+        })
+    }
+
+    /// Forget the transactions applied `RollbackWindow` blocks before `now`.
+    fn prune_applied_transactions(now: u64) {
+        if let Some(expired) = now.checked_sub(T::RollbackWindow::get() as u64) {
+            for txid in <AppliedInBlock>::take(expired) {
+                <AppliedTransactions>::remove(txid);
+            }
+        }
+    }
+
+    /// The UTXO at `out_point`, or a guarded output there as a native output owned by its owner.
+    fn spent_output(out_point: &H256) -> Option<TransactionOutput> {
+        <UtxoStore>::get(out_point).or_else( || {
+            <GuardedUtxos>::get(out_point).map( |guarded| TransactionOutput {
+                value: guarded.value,
+                pub_key: guarded.owner,
+                asset_id: NATIVE_ASSET,
+                ..Default::default()
+            })
+        })
+    }
+
+    fn update_storage(transaction: &Transaction) -> DispatchResult {
+        let reward = Self::transaction_fee(transaction)?;
+        let creator = transaction.inputs.first()
+            .and_then( |input| Self::spent_output(&input.out_point) )
+            .map( |utxo| utxo.pub_key );
+
         let new_total = <RewardTotal>::get()
             .checked_add(reward)
             .ok_or("reward overflow")?;
         <RewardTotal>::put(new_total);
+        Self::check_reward_high_water(new_total);
+        if reward > 0 {
+            <BlockFees>::mutate(Self::block_height(), |fees| *fees = fees.saturating_add(reward));
+        }
+        <BlockFeeRates>::append(Self::priority_for_fee(reward, transaction.encode().len() as u32));
 
         for input in &transaction.inputs {
-            <UtxoStore>::remove(input.out_point);
+            Self::remove_utxo(&input.out_point);
+            <GuardedUtxos>::remove(&input.out_point);
         }
 
         let mut index: u64 = 0;
         for output in &transaction.outputs {
             let hash = BlakeTwo256::hash_of( &(&transaction.encode(), index) );
             index = index.checked_add(1).ok_or("output index overflow")?;
-            <UtxoStore>::insert(hash, output);
+            if Self::is_burn_address(&output.pub_key) {
+                Self::burn(output)?;
+            } else if output.pub_key == FEE_ADDRESS {
+                // Already part of `reward`.
+            } else {
+                Self::insert_utxo(hash, output.clone());
+                if let Some(creator) = creator {
+                    <UtxoCreator>::insert(hash, creator);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Apply a block's transactions to `state` the way `spend` applies them to storage,
+    /// without touching storage. Each transaction is validated against `state` as left by the
+    /// ones before it, so it may spend outputs created earlier in the block but not an output,
+    /// guarded or not, that an earlier one spent. `state` is unchanged on error.
+    #[cfg(feature = "std")]
+    pub fn apply_block_body(
+        transactions: &[Transaction],
+        state: &mut BlockBodyState,
+    ) -> Result<(), &'static str> {
+        ensure!(
+            transactions.len() <= T::MaxTransactionsPerBlock::get() as usize,
+            "block transaction limit reached"
+        );
+        let mut applied = state.clone();
+        for transaction in transactions {
+            let valid = Self::check_transaction(&applied, transaction, &applied.utxos, false)?;
+            ensure!(valid.requires.is_empty(), "input utxo not found");
+            let encoded = transaction.encode();
+            let created: Vec<_> = transaction.outputs.iter()
+                .enumerate()
+                .filter( |(_, output)| applied.creates_utxo(output) )
+                .map( |(index, output)| (BlakeTwo256::hash_of( &(&encoded, index as u64) ), output.clone()) )
+                .collect();
+            for input in &transaction.inputs {
+                applied.utxos.remove(&input.out_point);
+                applied.guarded.remove(&input.out_point);
+                applied.minted_at.remove(&input.out_point);
+            }
+            applied.utxos.extend(created);
+        }
+        *state = applied;
+        Ok(())
+    }
+
+    /// Emit and clear the block's `BlockStateChanges`, leaving out UTXOs both created and
+    /// spent in it.
+    fn deposit_state_delta() {
+        let mut created = Vec::new();
+        let mut removed = Vec::new();
+        for (out_point, (existed, utxo)) in <BlockStateChanges>::drain() {
+            match utxo {
+                Some(utxo) => created.push((out_point, utxo)),
+                None if existed => removed.push(out_point),
+                None => {},
+            }
+        }
+        if !created.is_empty() || !removed.is_empty() {
+            Self::deposit_event(Event::BlockStateDelta(created, removed));
+        }
+    }
+
+    /// Warn once each time `total` rises above `RewardTotalHighWater`.
+    fn check_reward_high_water(total: Value) {
+        if total <= T::RewardTotalHighWater::get() {
+            <RewardTotalHighWarned>::kill();
+        } else if !<RewardTotalHighWarned>::get() {
+            <RewardTotalHighWarned>::put(true);
+            Self::deposit_event(Event::RewardTotalHigh(total));
+        }
+    }
+
+    fn burn(output: &TransactionOutput) -> DispatchResult {
+        if output.asset_id == NATIVE_ASSET {
+            let new_total = <BurnedTotal>::get().checked_add(output.value).ok_or("burned total overflow")?;
+            <BurnedTotal>::put(new_total);
+        } else {
+            let new_supply = <AssetSupply>::get(output.asset_id)
+                .checked_sub(output.value)
+                .ok_or("asset supply underflow")?;
+            <AssetSupply>::insert(output.asset_id, new_supply);
+            Self::deposit_event(Event::AssetSupplyChanged(output.asset_id, new_supply));
         }
         Ok(())
     }
 
+    /// Record when each of `authorities` joined the set, forgetting those that left, and return
+    /// the ones that have been in it for at least `MinRewardUptime` blocks.
+    fn reward_eligible(authorities: &[H256], now: u64) -> Vec<H256> {
+        let departed: Vec<_> = <AuthorityFirstSeen>::iter()
+            .map( |(authority, _)| authority )
+            .filter( |authority| !authorities.contains(authority) )
+            .collect();
+        for authority in departed {
+            <AuthorityFirstSeen>::remove(authority);
+        }
+        let uptime = T::MinRewardUptime::get() as u64;
+        let mut eligible = Vec::new();
+        for authority in authorities {
+            let first_seen = match <AuthorityFirstSeen>::get(authority) {
+                Some(first_seen) => first_seen,
+                None => {
+                    <AuthorityFirstSeen>::insert(authority, now);
+                    now
+                },
+            };
+            if now.saturating_sub(first_seen) >= uptime {
+                eligible.push(*authority);
+            }
+        }
+        eligible
+    }
+
     fn disperse_reward(authorities: &[H256]) {
-        let reward = <RewardTotal>::take();
-        let share_value: Value = reward
-            .checked_div(authorities.len() as Value)
-            .ok_or("No authorities")
-            .unwrap();
-        if share_value == 0 {return}
+        let now = Self::block_height();
+        if let Some(expired) = now.checked_sub(T::RewardHistoryRetention::get() as u64) {
+            <BlockRewards>::remove(expired);
+            <BlockFees>::remove(expired);
+        }
+        let eligible = Self::reward_eligible(authorities, now);
+        let authorities = eligible.as_slice();
+
+        let mut paid = Vec::new();
+        let mut total = <RewardTotal>::take();
+        // Fees of rolled back transactions were paid out once already.
+        let debt = <RewardDebt>::take();
+        let repaid = debt.min(total);
+        total -= repaid;
+        if debt > repaid {
+            <RewardDebt>::put(debt - repaid);
+        }
+        // Value carried over from blocks authored by a different authority set goes to that set,
+        // not to whoever is authoring now.
+        let carried = <CarriedReward>::take().min(total);
+        let earners = <RewardEarners>::take();
+        if carried > 0 && !earners.is_empty() && earners.as_slice() != authorities {
+            let share_value = carried / earners.len() as Value;
+            if share_value > 0 {
+                let shares: Vec<_> = earners.iter().map( |earner| (*earner, share_value) ).collect();
+                let unpaid = Self::pay_shares(&shares, now, &mut paid);
+                total = total - share_value * earners.len() as Value + unpaid;
+            }
+        }
+        Self::split_reward(authorities, total, now, &mut paid);
+
+        // Blocks without fees leave no record and emit nothing.
+        if !paid.is_empty() {
+            <BlockRewards>::insert(now, paid);
+        }
+        let still_held = <RewardTotal>::get();
+        Self::check_reward_high_water(still_held);
+        if still_held > 0 {
+            <CarriedReward>::put(still_held);
+            <RewardEarners>::put(authorities.to_vec());
+        }
+        if still_held > 0 && still_held < authorities.len() as Value {
+            if !<RewardDustSince>::exists() {
+                <RewardDustSince>::put(now);
+            }
+        } else {
+            <RewardDustSince>::kill();
+        }
+    }
+
+    /// Pay `total`, less any decay, to `authorities` as `reward_shares` splits it and leave the
+    /// rest in `RewardTotal`.
+    fn split_reward(authorities: &[H256], total: Value, now: u64, paid: &mut Vec<(H256, Value)>) {
+        if total == 0 {return}
+        if authorities.is_empty() {
+            <RewardTotal>::mutate( |carried| *carried = carried.saturating_add(total) );
+            return;
+        }
+        let held_back = T::RewardDecay::get() * total;
+        let reward = total - held_back;
+        let shares = Self::reward_shares(authorities, reward);
 
-        let remainder = reward
-            .checked_sub(share_value * authorities.len() as Value)
+        let remainder = shares.iter()
+            .try_fold(reward, |left, (_, share)| left.checked_sub(*share))
             .ok_or("Sub underflow")
             .unwrap();
-        
-        <RewardTotal>::put(remainder as Value);
 
-        for authrity in authorities {
+        Self::carry_remainder(remainder);
+        <RewardTotal>::mutate( |carried| *carried = carried.saturating_add(held_back) );
+
+        let unpaid = Self::pay_shares(&shares, now, paid);
+        <RewardTotal>::mutate( |carried| *carried = carried.saturating_add(unpaid) );
+    }
+
+    /// Split `reward` between `authorities` in proportion to their bonded stake, or equally if
+    /// none of them has bonded any.
+    fn reward_shares(authorities: &[H256], reward: Value) -> Vec<(H256, Value)> {
+        let stakes: Vec<_> = authorities.iter().map( |authority| (*authority, <Stake>::get(authority)) ).collect();
+        let total_stake = stakes.iter().fold(0, |total: Value, (_, stake)| total.saturating_add(*stake));
+        if total_stake == 0 {
+            let share_value: Value = reward
+                .checked_div(authorities.len() as Value)
+                .ok_or("No authorities")
+                .unwrap();
+            return authorities.iter().map( |authority| (*authority, share_value) ).collect();
+        }
+        stakes.into_iter()
+            .map( |(authority, stake)| {
+                (authority, multiply_by_rational(reward, stake, total_stake).unwrap_or(0))
+            })
+            .collect()
+    }
+
+    /// Mint each nonzero share to its authority, recording it in `paid`. Returns the value of the
+    /// shares that couldn't be paid to an authority with too many UTXOs.
+    fn pay_shares(shares: &[(H256, Value)], now: u64, paid: &mut Vec<(H256, Value)>) -> Value {
+        let mut unpaid: Value = 0;
+        for (authrity, share_value) in shares.iter().filter( |(_, share)| *share > 0 ) {
+            let share_value = *share_value;
+            let denomination = T::RewardDenomination::get();
+            let split = if T::RewardVestingPeriod::get() > 0 && T::RewardVestingTranches::get() > 0 {
+                Some(Self::pay_vested(authrity, share_value, now))
+            } else if denomination > 0 && share_value > denomination {
+                Some(Self::pay_denominated(authrity, share_value, denomination, now))
+            } else {
+                None
+            };
+            if let Some(minted) = split {
+                if minted {
+                    paid.push((*authrity, share_value));
+                } else {
+                    unpaid = unpaid.saturating_add(share_value);
+                    sp_runtime::print("Transaction reward carried over, authority has too many utxos");
+                }
+                continue;
+            }
+            let merged = Self::mergeable_reward(authrity, now);
+            if merged.is_none() && Self::ensure_owner_capacity(authrity, 1).is_err() {
+                unpaid = unpaid.saturating_add(share_value);
+                sp_runtime::print("Transaction reward carried over, authority has too many utxos");
+                continue;
+            }
             let utxo = TransactionOutput {
-                value: share_value,
+                value: merged.as_ref().map_or(share_value, |(_, previous)| previous.value.saturating_add(share_value)),
                 pub_key: *authrity,
+                lock_height: 0,
+                asset_id: NATIVE_ASSET,
+                metadata: Vec::new(),
+                memo: Vec::new(),
+                requires_gov_flag: None,
             };
 
-            let hash = BlakeTwo256::hash_of( &(&utxo, <frame_system::Module<T>>::block_number().saturated_into::<u64>()) );
+            let hash = BlakeTwo256::hash_of( &(&utxo, now) );
 
             if !<UtxoStore>::contains_key(hash) {
-                <UtxoStore>::insert(hash, utxo);
+                if let Some((previous, _)) = merged {
+                    Self::remove_utxo(&previous);
+                }
+                Self::insert_utxo(hash, utxo);
+                <CoinbaseUtxos>::insert(hash, now);
+                if T::RewardMergeWindow::get() > 0 || T::RewardEpochLength::get() > 0 {
+                    <LastReward>::insert(authrity, (hash, now));
+                }
+                paid.push((*authrity, share_value));
                 sp_runtime::print("Transaction reward sent to");
                 sp_runtime::print(hash.as_fixed_bytes() as &[u8]);
             } else {
                 sp_runtime::print("Transaction reward wasted due to hash collision");
             }
         }
+        unpaid
+    }
+
+    /// Mint `share_value` to `authority` as UTXOs of `denomination` and one for the remainder.
+    /// If the authority has no room for that many, the last UTXO it has room for takes the rest.
+    /// Mints nothing and returns false if it has no room at all.
+    fn pay_denominated(authority: &H256, share_value: Value, denomination: Value, now: u64) -> bool {
+        let room = T::MaxUtxosPerOwner::get().saturating_sub(Self::stored().utxo_count(authority)) as Value;
+        if room == 0 {
+            return false;
+        }
+        let mut whole = share_value / denomination;
+        if whole.saturating_add(if share_value % denomination > 0 { 1 } else { 0 }) > room {
+            whole = room - 1;
+        }
+        let rest = share_value - whole * denomination;
+        let pieces = (0..whole).map( |_| denomination ).chain(Some(rest).filter( |r| *r > 0 ));
+        for (index, value) in pieces.enumerate() {
+            Self::mint_reward_piece(authority, value, 0, now, index as u32);
+        }
+        true
+    }
+
+    /// Mint `share_value` to `authority` as `RewardVestingTranches` UTXOs unlocking evenly over
+    /// `RewardVestingPeriod`, none before `RewardCliffBlocks`. Mints nothing and returns false if
+    /// the authority has no room for them all.
+    fn pay_vested(authority: &H256, share_value: Value, now: u64) -> bool {
+        let period = T::RewardVestingPeriod::get() as u64;
+        let tranches = T::RewardVestingTranches::get();
+        let cliff = now.saturating_add(T::RewardCliffBlocks::get() as u64);
+        if Self::ensure_owner_capacity(authority, tranches).is_err() {
+            return false;
+        }
+        let mut vested: Value = 0;
+        for tranche in 1..=tranches {
+            let due = multiply_by_rational(share_value, tranche as u128, tranches as u128)
+                .unwrap_or(share_value);
+            let value = due.saturating_sub(vested);
+            vested = due;
+            if value > 0 {
+                let unlocks = now.saturating_add(period * tranche as u64 / tranches as u64).max(cliff);
+                Self::mint_reward_piece(authority, value, unlocks, now, tranche - 1);
+            }
+        }
+        true
+    }
+
+    /// Mint one of several reward UTXOs paid to `authority` in this block, told apart by `index`.
+    fn mint_reward_piece(authority: &H256, value: Value, lock_height: u64, now: u64, index: u32) {
+        let utxo = TransactionOutput {
+            value,
+            pub_key: *authority,
+            lock_height,
+            asset_id: NATIVE_ASSET,
+            metadata: Vec::new(),
+            memo: Vec::new(),
+            requires_gov_flag: None,
+        };
+        let hash = BlakeTwo256::hash_of( &(&utxo, now, index) );
+        if !<UtxoStore>::contains_key(hash) {
+            Self::insert_utxo(hash, utxo);
+            <CoinbaseUtxos>::insert(hash, now);
+        } else {
+            sp_runtime::print("Transaction reward wasted due to hash collision");
+        }
+    }
+
+    /// The authority's last reward UTXO, if it's unspent and was minted within the merge window
+    /// or in the current reward epoch.
+    fn mergeable_reward(authority: &H256, now: u64) -> Option<(H256, TransactionOutput)> {
+        let window = T::RewardMergeWindow::get() as u64;
+        let epoch = T::RewardEpochLength::get() as u64;
+        if window == 0 && epoch == 0 {
+            return None;
+        }
+        let (out_point, minted_at) = <LastReward>::get(authority)?;
+        let in_window = window > 0 && now.saturating_sub(minted_at) <= window;
+        let in_epoch = epoch > 0 && now / epoch == minted_at / epoch;
+        if (!in_window && !in_epoch) || <FrozenUtxos>::get(&out_point) {
+            return None;
+        }
+        <UtxoStore>::get(out_point).map( |utxo| (out_point, utxo) )
+    }
+
+    /// Translate every UTXO from the original `value` and `pub_key` layout, which has no locks,
+    /// assets or notes, and build the balance and owner indexes over them.
+    fn migrate_to_v1() {
+        <UtxoStore>::translate( |_, old: LegacyTransactionOutput| Some(TransactionOutput {
+            value: old.value,
+            pub_key: old.pub_key,
+            lock_height: 0,
+            asset_id: NATIVE_ASSET,
+            metadata: Vec::new(),
+            memo: Vec::new(),
+            requires_gov_flag: None,
+        }));
+        for (out_point, utxo) in <UtxoStore>::iter() {
+            <BalanceIndex>::mutate(utxo.pub_key, |balance| *balance = balance.saturating_add(utxo.value));
+            <OwnerUtxos>::append(utxo.pub_key, out_point);
+        }
+    }
+
+    fn carry_remainder(remainder: Value) {
+        let cap = T::MaxRewardRemainder::get();
+        if remainder > cap {
+            let excess = remainder - cap;
+            <BurnedTotal>::mutate(|total| *total = total.saturating_add(excess));
+            Self::deposit_event(Event::RemainderBurned(excess));
+            <RewardTotal>::put(cap);
+        } else {
+            <RewardTotal>::put(remainder);
+        }
+    }
+}
+
+sp_api::decl_runtime_apis! {
+    /// Queries for wallets and explorers against the UTXO module.
+    pub trait UtxoApi {
+        /// The fee a transaction of `tx_size` encoded bytes without a priority hint must pay to
+        /// reach a transaction pool priority of `target_priority`.
+        fn fee_for_priority(target_priority: u64, tx_size: u32) -> Value;
+
+        /// The key that created the UTXO at `out_point`, if it was created by a transaction.
+        fn creator_of(out_point: H256) -> Option<H256>;
+
+        /// The unspent output at `out_point`.
+        fn get_utxo(out_point: H256) -> Option<TransactionOutput>;
+
+        /// The fee rate `transaction` pays, in thousandths of a fee unit per encoded byte.
+        fn fee_rate(transaction: Transaction) -> Result<u64, Vec<u8>>;
+
+        /// The reward each authority received in `block`, while it's within the retention window.
+        fn rewards_in_block(block: u64) -> Vec<(H256, Value)>;
+
+        /// Whether the UTXO at `out_point` was minted as a block reward.
+        fn is_coinbase(out_point: H256) -> bool;
+
+        /// A fee rate, comparable to `fee_rate`, that recent blocks have been including.
+        fn suggested_fee_rate() -> u64;
+
+        /// The total native value of `pub_key`'s UTXOs.
+        fn balance_of(pub_key: H256) -> Value;
+
+        /// The out points and outputs of `pub_key`'s UTXOs.
+        fn utxos_of(pub_key: H256) -> Vec<(H256, TransactionOutput)>;
+
+        /// `pub_key`'s balance as of the block this is called at. Calling it at a past block
+        /// needs a node that keeps that block's state, e.g. one run with `--pruning archive`.
+        fn balance_of_at(pub_key: H256) -> Value;
+
+        /// The total native balance of `keys`, each key counted once.
+        fn proof_of_reserves(keys: Vec<H256>) -> Value;
+
+        /// The total native balance of the keys in `attestations`, if each comes with its
+        /// owner's signature over `(b"reserves", challenge)`.
+        fn attested_reserves(challenge: Vec<u8>, attestations: Vec<(H256, H512)>) -> Result<Value, Vec<u8>>;
+
+        /// The fees collected in blocks `from` to `to` inclusive, before dispersal. Fails for
+        /// ranges reaching past the reward history.
+        fn fees_collected(from: u64, to: u64) -> Result<Value, Vec<u8>>;
+
+        /// The out point and value of `pub_key`'s largest native UTXO that it can spend now.
+        fn largest_utxo(pub_key: H256) -> Option<(H256, Value)>;
+
+        /// Whether each of `out_points` exists and can be spent now.
+        fn inputs_available(out_points: Vec<H256>) -> Vec<bool>;
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,91 @@
+//! Benchmarks for `spend`, and for input signature verification, stopping at the first bad one
+//! or checking all.
+
+use super::*;
+use frame_benchmarking::{benchmarks, whitelisted_caller};
+use frame_system::RawOrigin;
+use sp_core::crypto::KeyTypeId;
+
+const KEY_TYPE: KeyTypeId = KeyTypeId(*b"utxo");
+const MAX_INPUTS: u32 = 100;
+const MAX_OUTPUTS: u32 = 100;
+
+/// A signed transaction spending `inputs` stored UTXOs of a fresh key into `outputs` outputs to
+/// distinct keys, paying the minimum fee. The first output carries `bytes` of metadata.
+fn signed_spend<T: Trait>(inputs: u32, outputs: u32, bytes: u32) -> Transaction {
+    let owner = sp_io::crypto::sr25519_generate(KEY_TYPE, None);
+    // Enough that even one input covers the fee and `MAX_OUTPUTS` outputs above the dust threshold.
+    let value = T::MinInputValue::get().max(T::DustThreshold::get())
+        .saturating_mul(MAX_OUTPUTS as Value)
+        .saturating_add(Module::<T>::required_fee(MAX_OUTPUTS as i64));
+
+    let mut transaction = Transaction::default();
+    for n in 0..inputs {
+        let out_point = BlakeTwo256::hash_of( &(&b"benchmark input"[..], n) );
+        Module::<T>::insert_utxo(out_point, TransactionOutput {
+            value,
+            pub_key: H256::from(owner.0),
+            ..Default::default()
+        });
+        transaction.inputs.push(TransactionInput { out_point, ..Default::default() });
+    }
+
+    let fee = Module::<T>::required_fee(outputs as i64 - inputs as i64);
+    let each = (value * inputs as Value - fee) / outputs as Value;
+    transaction.outputs = (0..outputs).map( |n| TransactionOutput {
+        value: each,
+        pub_key: H256::from_low_u64_be(n as u64 + 1),
+        ..Default::default()
+    }).collect();
+    transaction.outputs[0].metadata = vec![b'x'; bytes as usize];
+
+    let message = Module::<T>::get_simple_transaction(&transaction);
+    let sig = sp_io::crypto::sr25519_sign(KEY_TYPE, &owner, &message).expect("key was just generated");
+    for input in transaction.inputs.iter_mut() {
+        input.sig_script = H512::from(sig.0);
+    }
+    transaction
+}
+
+/// A message and `count` signatures over it, each by a fresh key.
+fn signed_inputs(count: u32) -> (Vec<u8>, Vec<(H512, H256)>) {
+    let message = b"benchmark transaction".to_vec();
+    let signed = (0..count).map( |_| {
+        let key = sp_io::crypto::sr25519_generate(KEY_TYPE, None);
+        let sig = sp_io::crypto::sr25519_sign(KEY_TYPE, &key, &message)
+            .expect("key was just generated");
+        (H512::from(sig.0), H256::from(key.0))
+    }).collect();
+    (message, signed)
+}
+
+benchmarks! {
+    _ { }
+
+    spend {
+        let i in 1 .. MAX_INPUTS;
+        let o in 1 .. MAX_OUTPUTS;
+        let b in 0 .. T::MaxMetadataLength::get();
+        let transaction = signed_spend::<T>(i, o, b);
+        let created = BlakeTwo256::hash_of( &(&transaction.encode(), 0u64) );
+    }: _(RawOrigin::Signed(whitelisted_caller()), transaction)
+    verify {
+        assert!(<UtxoStore>::contains_key(created));
+    }
+
+    verify_each_signature {
+        let i in 1 .. MAX_INPUTS;
+        let (message, signed) = signed_inputs(i);
+    }: {
+        for (sig, owner) in signed.iter() {
+            assert!(Module::<T>::signed_by_owner(&Module::<T>::stored(), sig, &message, owner, 0));
+        }
+    }
+
+    verify_all_signatures {
+        let i in 1 .. MAX_INPUTS;
+        let (message, signed) = signed_inputs(i);
+    }: {
+        assert!(Module::<T>::all_signed_by_owners(&Module::<T>::stored(), &message, &signed, 0));
+    }
+}
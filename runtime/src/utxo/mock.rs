@@ -0,0 +1,278 @@
+use crate::utxo::{
+	GenesisConfig, Module, OnTransactionApplied, Trait, Transaction, TransactionFilter,
+	TransactionOutput, Value, NATIVE_ASSET,
+};
+use sp_core::{sr25519, testing::TaskExecutor, traits::TaskExecutorExt, Pair, H256, H512};
+use frame_support::{impl_outer_event, impl_outer_origin, parameter_types, traits::Get, weights::Weight};
+use std::cell::RefCell;
+use sp_runtime::{
+	traits::{BlakeTwo256, Hash, IdentityLookup}, testing::Header, Perbill, Permill,
+};
+use frame_system as system;
+
+impl_outer_origin! {
+	pub enum Origin for Test {}
+}
+
+mod utxo {
+	pub use crate::utxo::Event;
+}
+
+impl_outer_event! {
+	pub enum TestEvent for Test {
+		system<T>,
+		utxo,
+	}
+}
+
+// Configure a mock runtime to test the module.
+
+#[derive(Clone, Eq, PartialEq)]
+pub struct Test;
+parameter_types! {
+	pub const BlockHashCount: u64 = 250;
+	pub const MaximumBlockWeight: Weight = 1024;
+	pub const MaximumBlockLength: u32 = 2 * 1024;
+	pub const AvailableBlockRatio: Perbill = Perbill::from_percent(75);
+}
+
+impl system::Trait for Test {
+	type BaseCallFilter = ();
+	type Origin = Origin;
+	type Call = ();
+	type Index = u64;
+	type BlockNumber = u64;
+	type Hash = H256;
+	type Hashing = BlakeTwo256;
+	type AccountId = u64;
+	type Lookup = IdentityLookup<Self::AccountId>;
+	type Header = Header;
+	type Event = TestEvent;
+	type BlockHashCount = BlockHashCount;
+	type MaximumBlockWeight = MaximumBlockWeight;
+	type DbWeight = ();
+	type BlockExecutionWeight = ();
+	type ExtrinsicBaseWeight = ();
+	type MaximumExtrinsicWeight = MaximumBlockWeight;
+	type MaximumBlockLength = MaximumBlockLength;
+	type AvailableBlockRatio = AvailableBlockRatio;
+	type Version = ();
+	type PalletInfo = ();
+	type AccountData = ();
+	type OnNewAccount = ();
+	type OnKilledAccount = ();
+	type SystemWeightInfo = ();
+}
+
+/// Declare module constants that tests can change through a setter. Every constant is
+/// reset to its default when a new test externality is built.
+macro_rules! test_params {
+	($( $name:ident: $ty:ty = $default:expr, $setter:ident; )*) => {
+		$(
+			pub struct $name;
+			impl $name {
+				fn with<R>(f: impl FnOnce(&RefCell<$ty>) -> R) -> R {
+					thread_local! {
+						static VALUE: RefCell<$ty> = RefCell::new($default);
+					}
+					VALUE.with(f)
+				}
+			}
+			impl Get<$ty> for $name {
+				fn get() -> $ty {
+					Self::with(|v| v.borrow().clone())
+				}
+			}
+			#[allow(dead_code)]
+			pub fn $setter(value: $ty) {
+				$name::with(|v| *v.borrow_mut() = value);
+			}
+		)*
+
+		fn reset_params() {
+			$( $setter($default); )*
+		}
+	};
+}
+
+test_params! {
+	MaxRewardRemainder: Value = Value::max_value(), set_max_reward_remainder;
+	MinOutputs: u32 = 1, set_min_outputs;
+	RewardMergeWindow: u32 = 0, set_reward_merge_window;
+	DustThreshold: Value = 1, set_dust_threshold;
+	MaxTransactionsPerBlock: u32 = u32::max_value(), set_max_transactions_per_block;
+	RewardDecay: Permill = Permill::from_percent(0), set_reward_decay;
+	MaxMetadataLength: u32 = 16, set_max_metadata_length;
+	StrictBalance: bool = false, set_strict_balance;
+	RewardHistoryRetention: u32 = 10, set_reward_history_retention;
+	CoinbaseMaturity: u32 = 0, set_coinbase_maturity;
+	FeeRateWindow: u32 = 3, set_fee_rate_window;
+	MaxBalancePerKey: Value = Value::max_value(), set_max_balance_per_key;
+	MaxUtxosPerOwner: u32 = 1_000, set_max_utxos_per_owner;
+	MinimumFee: Value = 0, set_minimum_fee;
+	ConsolidationDiscount: Permill = Permill::from_percent(0), set_consolidation_discount;
+	MaxMemoLength: u32 = 64, set_max_memo_length;
+	MinInputValue: Value = 0, set_min_input_value;
+	MaxFee: Value = Value::max_value(), set_max_fee;
+	RewardEpochLength: u32 = 0, set_reward_epoch_length;
+	UnbondingPeriod: u32 = 3, set_unbonding_period;
+	RewardTotalHighWater: Value = Value::max_value(), set_reward_total_high_water;
+	MaxSweepPerBlock: u32 = u32::max_value(), set_max_sweep_per_block;
+	BatchVerifyThreshold: u32 = 0, set_batch_verify_threshold;
+	RewardDustIdlePeriod: u32 = 5, set_reward_dust_idle_period;
+	RewardDustKey: H256 = H256::repeat_byte(0x7e), set_reward_dust_key;
+	MinRewardUptime: u32 = 0, set_min_reward_uptime;
+	RewardDenomination: Value = 0, set_reward_denomination;
+	MaxAssetsPerTransaction: u32 = u32::max_value(), set_max_assets_per_transaction;
+	RewardReorgWindow: u32 = 0, set_reward_reorg_window;
+	FeePerNewOutput: Value = 0, set_fee_per_new_output;
+	RewardVestingPeriod: u32 = 0, set_reward_vesting_period;
+	RewardVestingTranches: u32 = 1, set_reward_vesting_tranches;
+	RewardCliffBlocks: u32 = 0, set_reward_cliff_blocks;
+	ConsolidateChange: bool = false, set_consolidate_change;
+	EmitStateDelta: bool = false, set_emit_state_delta;
+	RollbackWindow: u32 = 10, set_rollback_window;
+}
+
+/// A key no transaction may pay to.
+pub const BLOCKED_KEY: H256 = H256::repeat_byte(0xbb);
+
+pub struct BlockedKeyFilter;
+impl TransactionFilter for BlockedKeyFilter {
+	fn allow(transaction: &Transaction) -> Result<(), &'static str> {
+		if transaction.outputs.iter().any( |output| output.pub_key == BLOCKED_KEY ) {
+			Err("output key is blocked")
+		} else {
+			Ok(())
+		}
+	}
+}
+
+thread_local! {
+	static APPLIED: RefCell<Vec<(H256, Vec<H256>, Vec<H256>)>> = RefCell::new(Vec::new());
+}
+
+/// Records every `on_applied` call, see `applied_transactions`.
+pub struct RecordApplied;
+impl OnTransactionApplied for RecordApplied {
+	fn on_applied(txid: H256, spent: &[H256], created: &[H256]) {
+		APPLIED.with(|a| a.borrow_mut().push((txid, spent.to_vec(), created.to_vec())));
+	}
+}
+
+/// The `(txid, spent, created)` of every transaction applied so far.
+pub fn applied_transactions() -> Vec<(H256, Vec<H256>, Vec<H256>)> {
+	APPLIED.with(|a| a.borrow().clone())
+}
+
+impl Trait for Test {
+	type Event = TestEvent;
+	type MaxRewardRemainder = MaxRewardRemainder;
+	type MinOutputs = MinOutputs;
+	type RewardMergeWindow = RewardMergeWindow;
+	type DustThreshold = DustThreshold;
+	type MaxTransactionsPerBlock = MaxTransactionsPerBlock;
+	type RewardDecay = RewardDecay;
+	type MaxMetadataLength = MaxMetadataLength;
+	type StrictBalance = StrictBalance;
+	type RewardHistoryRetention = RewardHistoryRetention;
+	type CoinbaseMaturity = CoinbaseMaturity;
+	type FeeRateWindow = FeeRateWindow;
+	type MaxBalancePerKey = MaxBalancePerKey;
+	type MaxUtxosPerOwner = MaxUtxosPerOwner;
+	type MinimumFee = MinimumFee;
+	type ConsolidationDiscount = ConsolidationDiscount;
+	type PreFilter = BlockedKeyFilter;
+	type PostSpend = RecordApplied;
+	type MaxMemoLength = MaxMemoLength;
+	type WeightInfo = ();
+	type MinInputValue = MinInputValue;
+	type MaxFee = MaxFee;
+	type RewardEpochLength = RewardEpochLength;
+	type UnbondingPeriod = UnbondingPeriod;
+	type RewardTotalHighWater = RewardTotalHighWater;
+	type MaxSweepPerBlock = MaxSweepPerBlock;
+	type BatchVerifyThreshold = BatchVerifyThreshold;
+	type RewardDustIdlePeriod = RewardDustIdlePeriod;
+	type RewardDustKey = RewardDustKey;
+	type MinRewardUptime = MinRewardUptime;
+	type RewardDenomination = RewardDenomination;
+	type MaxAssetsPerTransaction = MaxAssetsPerTransaction;
+	type ForceOrigin = system::EnsureRoot<u64>;
+	type RewardReorgWindow = RewardReorgWindow;
+	type FeePerNewOutput = FeePerNewOutput;
+	type RewardVestingPeriod = RewardVestingPeriod;
+	type RewardVestingTranches = RewardVestingTranches;
+	type RewardCliffBlocks = RewardCliffBlocks;
+	type ConsolidateChange = ConsolidateChange;
+	type EmitStateDelta = EmitStateDelta;
+	type RollbackWindow = RollbackWindow;
+}
+
+pub type System = system::Module<Test>;
+pub type Utxo = Module<Test>;
+
+pub const GENESIS_VALUE: Value = 100;
+
+pub fn alice() -> sr25519::Pair {
+	sr25519::Pair::from_string("//Alice", None).expect("static values are valid; qed")
+}
+
+pub fn bob() -> sr25519::Pair {
+	sr25519::Pair::from_string("//Bob", None).expect("static values are valid; qed")
+}
+
+pub fn key_of(pair: &sr25519::Pair) -> H256 {
+	H256::from(pair.public().0)
+}
+
+pub fn genesis_utxo() -> TransactionOutput {
+	TransactionOutput {
+		value: GENESIS_VALUE,
+		pub_key: key_of(&alice()),
+		lock_height: 0,
+		asset_id: NATIVE_ASSET,
+		metadata: Vec::new(),
+		memo: Vec::new(),
+		requires_gov_flag: None,
+	}
+}
+
+pub fn genesis_out_point() -> H256 {
+	BlakeTwo256::hash_of(&genesis_utxo())
+}
+
+/// Sign every input of `transaction` with `pair`.
+pub fn sign(transaction: &mut Transaction, pair: &sr25519::Pair) {
+	let message = Utxo::get_simple_transaction(transaction);
+	let sig_script = H512::from(pair.sign(&message).0);
+	for input in transaction.inputs.iter_mut() {
+		input.sig_script = sig_script;
+	}
+}
+
+/// The out point of the output at `index` created by `transaction`.
+pub fn out_point(transaction: &Transaction, index: u64) -> H256 {
+	BlakeTwo256::hash_of( &(&codec::Encode::encode(transaction), index) )
+}
+
+// Build genesis storage according to the mock runtime.
+pub fn new_test_ext() -> sp_io::TestExternalities {
+	new_test_ext_with(GenesisConfig {
+		genesis_utxos: vec![genesis_utxo()],
+		total_genesis_supply: GENESIS_VALUE,
+		..Default::default()
+	})
+}
+
+pub fn new_test_ext_with(config: GenesisConfig) -> sp_io::TestExternalities {
+	reset_params();
+	APPLIED.with(|a| a.borrow_mut().clear());
+	let mut t = system::GenesisConfig::default().build_storage::<Test>().unwrap();
+	config.assimilate_storage::<Test>(&mut t).unwrap();
+	let mut ext = sp_io::TestExternalities::new(t);
+	// Needed by the signature batch block import holds open.
+	ext.register_extension(TaskExecutorExt::new(TaskExecutor::new()));
+	ext.execute_with(|| System::set_block_number(1));
+	ext
+}
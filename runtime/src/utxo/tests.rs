@@ -0,0 +1,2653 @@
+use crate::utxo::{
+	mock::*, decode_canonical_transaction, decrypt_memo, encrypt_memo, format_address,
+	format_outpoint, from_compact, parse_address, parse_genesis_utxos, parse_outpoint,
+	parse_utxo_dump, to_compact, Airdrop, BalanceIndex, BlockBodyState, BlockFeeRates, BlockRewards,
+	Call, Event, GenesisConfig, GuardedOutput, HashLockOutput, IntegrityFault,
+	LegacyTransactionOutput, OutputTemplate, OwnerUtxos, RewardTotal, StorageVersion, TemplateSlot,
+	Transaction, TransactionInput, TransactionOutput, TransactionTemplate, UtxoStore, Value,
+	WeightInfo, BURN_ADDRESS, FEE_ADDRESS, MEMO_OVERHEAD, NATIVE_ASSET,
+};
+use codec::Encode;
+use frame_support::{
+	assert_noop, assert_ok, dispatch::DispatchResult, traits::{OnInitialize, OnRuntimeUpgrade},
+	weights::GetDispatchInfo, IterableStorageMap, StorageMap, StorageValue,
+};
+use sp_core::{Pair, H256, H512};
+use sp_runtime::{traits::{BadOrigin, BlakeTwo256, Hash}, Permill};
+
+fn transfer(out_point: H256, value: u128, to: H256) -> Transaction {
+	Transaction {
+		inputs: vec![TransactionInput { out_point, ..Default::default() }],
+		outputs: vec![TransactionOutput {
+			value,
+			pub_key: to,
+			lock_height: 0,
+			asset_id: NATIVE_ASSET,
+			metadata: Vec::new(),
+			memo: Vec::new(),
+			requires_gov_flag: None,
+		}],
+		nonce: 0,
+		priority_hint: 0,
+	}
+}
+
+fn refresh_sig(pair: &sp_core::sr25519::Pair, out_point: H256, lock_height: u64) -> H512 {
+	H512::from(pair.sign(&(&b"refresh"[..], &out_point, lock_height).encode()).0)
+}
+
+#[test]
+fn spend_moves_value_to_new_owner() {
+	new_test_ext().execute_with(|| {
+		let mut tx = transfer(genesis_out_point(), 50, key_of(&bob()));
+		sign(&mut tx, &alice());
+
+		assert_ok!(Utxo::spend(Origin::signed(0), tx.clone()));
+		assert!(!UtxoStore::contains_key(genesis_out_point()));
+		assert_eq!(UtxoStore::get(out_point(&tx, 0)).unwrap().value, 50);
+		assert_eq!(Utxo::reward_total(), 50);
+	});
+}
+
+#[test]
+fn refresh_extends_lock_and_keeps_value() {
+	new_test_ext().execute_with(|| {
+		let sig = refresh_sig(&alice(), genesis_out_point(), 10);
+		assert_ok!(Utxo::refresh(Origin::signed(0), genesis_out_point(), 10, sig));
+
+		let new_out_point = BlakeTwo256::hash_of( &(&genesis_out_point(), 10u64) );
+		let refreshed = UtxoStore::get(new_out_point).unwrap();
+		assert_eq!(refreshed.value, GENESIS_VALUE);
+		assert_eq!(refreshed.pub_key, key_of(&alice()));
+		assert_eq!(refreshed.lock_height, 10);
+		assert!(!UtxoStore::contains_key(genesis_out_point()));
+		assert!(System::events().iter().any(|r| r.event == TestEvent::utxo(
+			Event::UtxoRefreshed(genesis_out_point(), new_out_point)
+		)));
+
+		// The refreshed output can't be spent before its lock height.
+		let mut tx = transfer(new_out_point, 50, key_of(&bob()));
+		sign(&mut tx, &alice());
+		assert_noop!(Utxo::spend(Origin::signed(0), tx.clone()), "input utxo is time-locked");
+
+		System::set_block_number(10);
+		assert_ok!(Utxo::spend(Origin::signed(0), tx));
+	});
+}
+
+#[test]
+fn refresh_rejects_decreasing_lock() {
+	new_test_ext().execute_with(|| {
+		let sig = refresh_sig(&alice(), genesis_out_point(), 10);
+		assert_ok!(Utxo::refresh(Origin::signed(0), genesis_out_point(), 10, sig));
+		let locked = BlakeTwo256::hash_of( &(&genesis_out_point(), 10u64) );
+
+		let sig = refresh_sig(&alice(), locked, 5);
+		assert_noop!(Utxo::refresh(Origin::signed(0), locked, 5, sig), "lock height must not decrease");
+
+		System::set_block_number(20);
+		let sig = refresh_sig(&alice(), locked, 15);
+		assert_noop!(Utxo::refresh(Origin::signed(0), locked, 15, sig), "lock height must not be in the past");
+	});
+}
+
+#[test]
+fn refresh_requires_owner_signature() {
+	new_test_ext().execute_with(|| {
+		let sig = refresh_sig(&bob(), genesis_out_point(), 10);
+		assert_noop!(
+			Utxo::refresh(Origin::signed(0), genesis_out_point(), 10, sig),
+			"signature must be valid"
+		);
+	});
+}
+
+#[test]
+fn remainder_above_cap_is_burned() {
+	new_test_ext().execute_with(|| {
+		set_max_reward_remainder(1);
+		let authorities = [H256::repeat_byte(1), H256::repeat_byte(2), H256::repeat_byte(3)];
+
+		// 2 can't be split between 3 authorities, so it's all carried and 1 is over the cap.
+		RewardTotal::put(2);
+		Utxo::disperse_reward(&authorities);
+
+		assert_eq!(Utxo::reward_total(), 1);
+		assert_eq!(Utxo::burned_total(), 1);
+		assert!(System::events().iter().any(|r| r.event == TestEvent::utxo(Event::RemainderBurned(1))));
+
+		// A remainder within the cap is carried untouched.
+		RewardTotal::put(7);
+		Utxo::disperse_reward(&authorities);
+		assert_eq!(Utxo::reward_total(), 1);
+		assert_eq!(Utxo::burned_total(), 1);
+	});
+}
+
+fn asset_utxo(value: u128, asset_id: u32) -> TransactionOutput {
+	TransactionOutput {
+		value,
+		pub_key: key_of(&bob()),
+		lock_height: 0,
+		asset_id,
+		metadata: Vec::new(),
+		memo: Vec::new(),
+		requires_gov_flag: None,
+	}
+}
+
+#[test]
+fn genesis_accepts_matching_asset_supplies() {
+	new_test_ext_with(GenesisConfig {
+		genesis_utxos: vec![genesis_utxo(), asset_utxo(30, 1), asset_utxo(70, 1), asset_utxo(5, 2)],
+		asset_supplies: vec![(1, 100), (2, 5)].into_iter().collect(),
+		total_genesis_supply: GENESIS_VALUE,
+		..Default::default()
+	}).execute_with(|| {
+		assert!(UtxoStore::contains_key(BlakeTwo256::hash_of(&asset_utxo(70, 1))));
+	});
+}
+
+#[test]
+#[should_panic(expected = "genesis outputs of asset 1 total 100 but declared supply is 90")]
+fn genesis_rejects_mismatched_asset_supply() {
+	new_test_ext_with(GenesisConfig {
+		genesis_utxos: vec![genesis_utxo(), asset_utxo(30, 1), asset_utxo(70, 1)],
+		asset_supplies: vec![(1, 90)].into_iter().collect(),
+		total_genesis_supply: GENESIS_VALUE,
+		..Default::default()
+	});
+}
+
+#[test]
+#[should_panic(expected = "genesis asset 2 has no declared supply")]
+fn genesis_rejects_undeclared_asset() {
+	new_test_ext_with(GenesisConfig {
+		genesis_utxos: vec![genesis_utxo(), asset_utxo(5, 2)],
+		total_genesis_supply: GENESIS_VALUE,
+		..Default::default()
+	});
+}
+
+#[test]
+fn non_native_assets_must_balance() {
+	let asset = TransactionOutput { pub_key: key_of(&alice()), ..asset_utxo(50, 1) };
+	new_test_ext_with(GenesisConfig {
+		genesis_utxos: vec![genesis_utxo(), asset.clone()],
+		asset_supplies: vec![(1, 50)].into_iter().collect(),
+		total_genesis_supply: GENESIS_VALUE,
+		..Default::default()
+	}).execute_with(|| {
+		let asset_out_point = BlakeTwo256::hash_of(&asset);
+		let mut tx = transfer(asset_out_point, 40, key_of(&bob()));
+		tx.outputs[0].asset_id = 1;
+		sign(&mut tx, &alice());
+		assert_noop!(Utxo::spend(Origin::signed(0), tx), "asset input and output values must match");
+
+		let mut tx = transfer(asset_out_point, 50, key_of(&bob()));
+		tx.outputs[0].asset_id = 1;
+		sign(&mut tx, &alice());
+		assert_ok!(Utxo::spend(Origin::signed(0), tx));
+		assert_eq!(Utxo::reward_total(), 0);
+	});
+}
+
+#[test]
+fn spend_is_rejected_inside_maintenance_window() {
+	new_test_ext().execute_with(|| {
+		assert_noop!(Utxo::set_maintenance_windows(Origin::signed(0), vec![(5, 8)]), BadOrigin);
+		assert_ok!(Utxo::set_maintenance_windows(Origin::root(), vec![(5, 8)]));
+
+		let mut tx = transfer(genesis_out_point(), 50, key_of(&bob()));
+		sign(&mut tx, &alice());
+
+		for block in 5..=8 {
+			System::set_block_number(block);
+			assert_noop!(Utxo::spend(Origin::signed(0), tx.clone()), "outside acceptance window");
+		}
+
+		System::set_block_number(9);
+		assert_ok!(Utxo::spend(Origin::signed(0), tx));
+	});
+}
+
+#[test]
+fn mint_and_burn_emit_asset_supply_events() {
+	new_test_ext().execute_with(|| {
+		assert_noop!(Utxo::mint_asset(Origin::signed(0), 1, 40, key_of(&alice())), BadOrigin);
+		assert_noop!(
+			Utxo::mint_asset(Origin::root(), NATIVE_ASSET, 40, key_of(&alice())),
+			"native asset can't be minted"
+		);
+		assert_ok!(Utxo::mint_asset(Origin::root(), 1, 40, key_of(&alice())));
+		assert_eq!(Utxo::asset_supply(1), 40);
+		assert_eq!(
+			System::events().last().unwrap().event,
+			TestEvent::utxo(Event::AssetSupplyChanged(1, 40))
+		);
+
+		let minted = UtxoStore::iter()
+			.find( |(_, utxo)| utxo.asset_id == 1 )
+			.map( |(out_point, _)| out_point )
+			.unwrap();
+		let mut tx = Transaction {
+			inputs: vec![TransactionInput { out_point: minted, ..Default::default() }],
+			outputs: vec![
+				TransactionOutput { pub_key: BURN_ADDRESS, ..asset_utxo(15, 1) },
+				asset_utxo(25, 1),
+			],
+			nonce: 0,
+			priority_hint: 0,
+		};
+		sign(&mut tx, &alice());
+		assert_ok!(Utxo::spend(Origin::signed(0), tx.clone()));
+
+		assert_eq!(Utxo::asset_supply(1), 25);
+		assert!(!UtxoStore::contains_key(out_point(&tx, 0)));
+		assert!(System::events().iter().any( |r| r.event == TestEvent::utxo(Event::AssetSupplyChanged(1, 25)) ));
+	});
+}
+
+#[test]
+fn min_outputs_policy_is_enforced() {
+	new_test_ext().execute_with(|| {
+		let mut tx = transfer(genesis_out_point(), 50, key_of(&bob()));
+		sign(&mut tx, &alice());
+
+		set_min_outputs(2);
+		assert_noop!(Utxo::spend(Origin::signed(0), tx.clone()), "too few outputs");
+
+		set_min_outputs(1);
+		assert_ok!(Utxo::spend(Origin::signed(0), tx));
+	});
+}
+
+#[test]
+fn fee_for_priority_reaches_target() {
+	new_test_ext().execute_with(|| {
+		let tx_size = transfer(genesis_out_point(), 0, key_of(&bob())).encode().len() as u32;
+
+		for target in [1u64, 7, 50, 250, 256 * 20, 256 * 250 + 1].iter() {
+			let fee = Utxo::fee_for_priority(*target, tx_size);
+			let mut tx = transfer(genesis_out_point(), GENESIS_VALUE - fee, key_of(&bob()));
+			sign(&mut tx, &alice());
+			assert_eq!(tx.encode().len() as u32, tx_size);
+			assert!(Utxo::validate_transaction(&tx).unwrap().priority >= *target);
+
+			// The fee is the smallest one that reaches the target.
+			let mut tx = transfer(genesis_out_point(), GENESIS_VALUE - fee + 1, key_of(&bob()));
+			sign(&mut tx, &alice());
+			assert!(Utxo::validate_transaction(&tx).unwrap().priority < *target);
+		}
+	});
+}
+
+#[test]
+fn spent_outputs_record_their_creator() {
+	new_test_ext().execute_with(|| {
+		assert_eq!(Utxo::creator_of(genesis_out_point()), None);
+
+		let mut tx = transfer(genesis_out_point(), 50, key_of(&bob()));
+		sign(&mut tx, &alice());
+		assert_ok!(Utxo::spend(Origin::signed(0), tx.clone()));
+		assert_eq!(Utxo::creator_of(out_point(&tx, 0)), Some(key_of(&alice())));
+
+		let mut onward = transfer(out_point(&tx, 0), 50, key_of(&alice()));
+		sign(&mut onward, &bob());
+		assert_ok!(Utxo::spend(Origin::signed(0), onward.clone()));
+		assert_eq!(Utxo::creator_of(out_point(&tx, 0)), None);
+		assert_eq!(Utxo::creator_of(out_point(&onward, 0)), Some(key_of(&bob())));
+	});
+}
+
+fn rewards_of(authority: H256) -> Vec<Value> {
+	UtxoStore::iter()
+		.filter( |(_, utxo)| utxo.pub_key == authority )
+		.map( |(_, utxo)| utxo.value )
+		.collect()
+}
+
+#[test]
+fn consecutive_rewards_merge_within_window() {
+	new_test_ext().execute_with(|| {
+		set_reward_merge_window(2);
+		let authority = H256::repeat_byte(1);
+
+		for block in 1..=3 {
+			System::set_block_number(block);
+			RewardTotal::put(10);
+			Utxo::disperse_reward(&[authority]);
+		}
+		assert_eq!(rewards_of(authority), vec![30]);
+
+		// A reward minted after the window starts a new UTXO.
+		System::set_block_number(6);
+		RewardTotal::put(10);
+		Utxo::disperse_reward(&[authority]);
+		let mut rewards = rewards_of(authority);
+		rewards.sort();
+		assert_eq!(rewards, vec![10, 30]);
+	});
+}
+
+#[test]
+fn frozen_rewards_are_not_merged() {
+	new_test_ext().execute_with(|| {
+		set_reward_merge_window(2);
+		let authority = H256::repeat_byte(1);
+
+		System::set_block_number(1);
+		RewardTotal::put(10);
+		Utxo::disperse_reward(&[authority]);
+		let (frozen, _) = UtxoStore::iter().find( |(_, utxo)| utxo.pub_key == authority ).unwrap();
+		assert_ok!(Utxo::set_frozen(Origin::root(), frozen, true));
+
+		System::set_block_number(2);
+		RewardTotal::put(10);
+		Utxo::disperse_reward(&[authority]);
+		assert_eq!(Utxo::is_frozen(frozen), true);
+		assert_eq!(UtxoStore::get(frozen).unwrap().value, 10);
+		let mut rewards = rewards_of(authority);
+		rewards.sort();
+		assert_eq!(rewards, vec![10, 10]);
+	});
+}
+
+#[test]
+fn rewards_do_not_merge_when_disabled() {
+	new_test_ext().execute_with(|| {
+		let authority = H256::repeat_byte(1);
+		for block in 1..=2 {
+			System::set_block_number(block);
+			RewardTotal::put(10);
+			Utxo::disperse_reward(&[authority]);
+		}
+		assert_eq!(rewards_of(authority), vec![10, 10]);
+	});
+}
+
+#[test]
+fn parse_utxo_dump_reads_entries() {
+	let dump = format!("0x{},25\n\n{}, 7\n", hex_of(key_of(&bob())), hex_of(H256::repeat_byte(3)));
+	assert_eq!(
+		parse_utxo_dump(&dump).unwrap(),
+		vec![(key_of(&bob()), 25), (H256::repeat_byte(3), 7)]
+	);
+	assert!(parse_utxo_dump("1234,5").unwrap_err().contains("line 1"));
+	assert!(parse_utxo_dump(&format!("{},five", hex_of(key_of(&bob())))).is_err());
+	assert!(parse_utxo_dump(&format!("{},5,6", hex_of(key_of(&bob())))).is_err());
+}
+
+#[test]
+fn genesis_list_becomes_genesis_utxos() {
+	let list = format!("{},100\n{},7\n", hex_of(key_of(&alice())), hex_of(key_of(&bob())));
+	let utxos = parse_genesis_utxos(&list).unwrap();
+	assert_eq!(utxos[0], genesis_utxo());
+	assert_eq!(utxos[1].pub_key, key_of(&bob()));
+	assert_eq!(utxos[1].value, 7);
+
+	let genesis = GenesisConfig { genesis_utxos: utxos, total_genesis_supply: 107, ..Default::default() };
+	new_test_ext_with(genesis).execute_with(|| {
+		assert_eq!(Utxo::get_utxo(genesis_out_point()), Some(genesis_utxo()));
+		assert_eq!(Utxo::balance_of(key_of(&bob())), 7);
+	});
+}
+
+#[test]
+fn genesis_list_rejects_malformed_entries() {
+	let alice = hex_of(key_of(&alice()));
+	let duplicate = format!("{},5\n{},6", alice, alice);
+	assert!(parse_genesis_utxos(&duplicate).unwrap_err().contains("more than once"));
+	assert!(parse_genesis_utxos(&format!("{},0", alice)).unwrap_err().contains("zero value"));
+	assert!(parse_genesis_utxos("xyz,5").unwrap_err().contains("line 1"));
+	assert!(parse_genesis_utxos(&format!("{},-5", alice)).is_err());
+}
+
+fn hex_of(key: H256) -> String {
+	key.as_bytes().iter().map( |b| format!("{:02x}", b) ).collect()
+}
+
+#[test]
+fn external_utxos_are_imported_once_and_spendable() {
+	new_test_ext().execute_with(|| {
+		let entries = vec![(key_of(&bob()), 25), (H256::repeat_byte(3), 7)];
+		assert_noop!(Utxo::import_external_utxos(Origin::signed(0), entries.clone()), BadOrigin);
+		assert_noop!(
+			Utxo::import_external_utxos(Origin::root(), vec![(key_of(&bob()), 25), (key_of(&bob()), 25)]),
+			"each entry must only be imported once"
+		);
+		set_dust_threshold(10);
+		assert_noop!(
+			Utxo::import_external_utxos(Origin::root(), entries.clone()),
+			"output value below dust threshold"
+		);
+		set_dust_threshold(1);
+
+		assert_ok!(Utxo::import_external_utxos(Origin::root(), entries.clone()));
+		assert_noop!(
+			Utxo::import_external_utxos(Origin::root(), entries),
+			"external utxos already imported"
+		);
+
+		let imported = BlakeTwo256::hash_of( &(&b"import"[..], &TransactionOutput {
+			value: 25,
+			pub_key: key_of(&bob()),
+			lock_height: 0,
+			asset_id: NATIVE_ASSET,
+			metadata: Vec::new(),
+			memo: Vec::new(),
+			requires_gov_flag: None,
+		}, 0u64) );
+		let mut tx = transfer(imported, 25, key_of(&alice()));
+		sign(&mut tx, &bob());
+		assert_ok!(Utxo::spend(Origin::signed(0), tx));
+	});
+}
+
+#[test]
+fn outputs_below_dust_are_rejected() {
+	new_test_ext().execute_with(|| {
+		set_dust_threshold(20);
+		let mut tx = transfer(genesis_out_point(), 19, key_of(&bob()));
+		sign(&mut tx, &alice());
+		assert_noop!(Utxo::spend(Origin::signed(0), tx), "output value below dust threshold");
+	});
+}
+
+/// Split the genesis UTXO into outputs of `values`, owned by Alice.
+fn split_genesis(values: &[Value]) -> Transaction {
+	let mut tx = Transaction {
+		inputs: vec![TransactionInput { out_point: genesis_out_point(), ..Default::default() }],
+		outputs: values.iter().map( |value| TransactionOutput {
+			value: *value,
+			pub_key: key_of(&alice()),
+			lock_height: 0,
+			asset_id: NATIVE_ASSET,
+			metadata: Vec::new(),
+			memo: Vec::new(),
+			requires_gov_flag: None,
+		}).collect(),
+		nonce: 0,
+		priority_hint: 0,
+	};
+	sign(&mut tx, &alice());
+	assert_ok!(Utxo::spend(Origin::signed(0), tx.clone()));
+	tx
+}
+
+#[test]
+fn block_transaction_limit_is_enforced_and_reset() {
+	new_test_ext().execute_with(|| {
+		set_max_transactions_per_block(2);
+		let split = split_genesis(&[10, 20, 30, 40]);
+
+		let mut txs: Vec<_> = (0..3).map( |index| {
+			let mut tx = transfer(out_point(&split, index), 5, key_of(&bob()));
+			sign(&mut tx, &alice());
+			tx
+		}).collect();
+
+		assert_ok!(Utxo::spend(Origin::signed(0), txs.remove(0)));
+		assert_noop!(Utxo::spend(Origin::signed(0), txs[0].clone()), "block transaction limit reached");
+
+		System::set_block_number(2);
+		Utxo::on_initialize(2);
+		assert_ok!(Utxo::spend(Origin::signed(0), txs.remove(0)));
+		assert_ok!(Utxo::spend(Origin::signed(0), txs.remove(0)));
+		assert_noop!(
+			Utxo::spend(Origin::signed(0), transfer(out_point(&split, 3), 5, key_of(&bob()))),
+			"block transaction limit reached"
+		);
+	});
+}
+
+fn create_voucher(secret: &[u8]) -> H256 {
+	let secret_hash = BlakeTwo256::hash(secret);
+	let sig = H512::from(alice().sign(&(&b"voucher"[..], &genesis_out_point(), secret_hash).encode()).0);
+	assert_ok!(Utxo::create_voucher(Origin::signed(0), genesis_out_point(), secret_hash, sig));
+	BlakeTwo256::hash_of( &(&genesis_out_point(), &HashLockOutput { value: GENESIS_VALUE, secret_hash }) )
+}
+
+fn commitment(preimage: &[u8], voucher: H256, destination: H256) -> H256 {
+	BlakeTwo256::hash_of( &(&preimage.to_vec(), &voucher, &destination) )
+}
+
+#[test]
+fn voucher_is_redeemed_with_correct_preimage() {
+	new_test_ext().execute_with(|| {
+		let voucher = create_voucher(b"gift");
+		assert!(!UtxoStore::contains_key(genesis_out_point()));
+
+		let destination = key_of(&bob());
+		assert_ok!(Utxo::commit_redeem(Origin::signed(0), commitment(b"gift", voucher, destination)));
+		assert_noop!(
+			Utxo::redeem_voucher(Origin::signed(0), voucher, b"gift".to_vec(), destination),
+			"redemption must be committed in an earlier block"
+		);
+
+		System::set_block_number(2);
+		assert_ok!(Utxo::redeem_voucher(Origin::signed(0), voucher, b"gift".to_vec(), destination));
+		assert!(Utxo::hash_lock(voucher).is_none());
+		let redeemed = UtxoStore::get(BlakeTwo256::hash_of( &(&voucher, &destination) )).unwrap();
+		assert_eq!((redeemed.value, redeemed.pub_key), (GENESIS_VALUE, destination));
+	});
+}
+
+#[test]
+fn voucher_rejects_wrong_preimage_and_redirection() {
+	new_test_ext().execute_with(|| {
+		let voucher = create_voucher(b"gift");
+		let destination = key_of(&bob());
+		assert_ok!(Utxo::commit_redeem(Origin::signed(0), commitment(b"gift", voucher, destination)));
+		System::set_block_number(2);
+
+		assert_noop!(
+			Utxo::redeem_voucher(Origin::signed(0), voucher, b"guess".to_vec(), destination),
+			"preimage does not match"
+		);
+		// Knowing the preimage isn't enough to redeem to a destination nobody committed to.
+		assert_noop!(
+			Utxo::redeem_voucher(Origin::signed(0), voucher, b"gift".to_vec(), key_of(&alice())),
+			"redemption was not committed"
+		);
+	});
+}
+
+#[test]
+fn vouchers_cannot_be_redeemed_to_the_fee_address() {
+	new_test_ext().execute_with(|| {
+		let voucher = create_voucher(b"gift");
+		assert_ok!(Utxo::commit_redeem(Origin::signed(0), commitment(b"gift", voucher, FEE_ADDRESS)));
+		System::set_block_number(2);
+		assert_noop!(
+			Utxo::redeem_voucher(Origin::signed(0), voucher, b"gift".to_vec(), FEE_ADDRESS),
+			"can't redeem to the fee address"
+		);
+	});
+}
+
+#[test]
+fn assets_cannot_be_minted_to_the_fee_address() {
+	new_test_ext().execute_with(|| {
+		assert_noop!(
+			Utxo::mint_asset(Origin::root(), 1, 40, FEE_ADDRESS),
+			"can't mint to the fee address"
+		);
+	});
+}
+
+#[test]
+fn external_utxos_cannot_be_imported_to_the_fee_address() {
+	new_test_ext().execute_with(|| {
+		assert_noop!(
+			Utxo::import_external_utxos(Origin::root(), vec![(key_of(&bob()), 25), (FEE_ADDRESS, 7)]),
+			"can't import to the fee address"
+		);
+		assert!(!Utxo::external_import_done());
+	});
+}
+
+#[test]
+fn reward_decay_smooths_payouts() {
+	new_test_ext().execute_with(|| {
+		set_reward_decay(Permill::from_percent(50));
+		let authority = H256::repeat_byte(1);
+
+		// A burst of fees is paid out over the following blocks.
+		RewardTotal::put(200);
+		Utxo::disperse_reward(&[authority]);
+		assert_eq!(Utxo::reward_total(), 100);
+
+		System::set_block_number(2);
+		Utxo::disperse_reward(&[authority]);
+		assert_eq!(Utxo::reward_total(), 50);
+
+		System::set_block_number(3);
+		RewardTotal::mutate( |total| *total += 100 );
+		Utxo::disperse_reward(&[authority]);
+		assert_eq!(Utxo::reward_total(), 75);
+
+		let mut rewards = rewards_of(authority);
+		rewards.sort();
+		assert_eq!(rewards, vec![50, 75, 100]);
+	});
+}
+
+#[test]
+fn output_metadata_round_trips_and_is_bounded() {
+	new_test_ext().execute_with(|| {
+		let mut tx = transfer(genesis_out_point(), 50, key_of(&bob()));
+		tx.outputs[0].metadata = vec![b'x'; 17];
+		sign(&mut tx, &alice());
+		assert_noop!(Utxo::spend(Origin::signed(0), tx), "output metadata too long");
+
+		let mut tx = transfer(genesis_out_point(), 50, key_of(&bob()));
+		tx.outputs[0].metadata = b"savings".to_vec();
+		sign(&mut tx, &alice());
+		assert_ok!(Utxo::spend(Origin::signed(0), tx.clone()));
+		assert_eq!(Utxo::get_utxo(out_point(&tx, 0)).unwrap().metadata, b"savings".to_vec());
+
+		// Metadata doesn't get in the way of spending.
+		let mut onward = transfer(out_point(&tx, 0), 50, key_of(&alice()));
+		sign(&mut onward, &bob());
+		assert_ok!(Utxo::spend(Origin::signed(0), onward));
+	});
+}
+
+#[test]
+fn double_spend_in_one_block_has_a_clear_error() {
+	new_test_ext().execute_with(|| {
+		let mut first = transfer(genesis_out_point(), 50, key_of(&bob()));
+		sign(&mut first, &alice());
+		let mut second = transfer(genesis_out_point(), 60, key_of(&bob()));
+		sign(&mut second, &alice());
+
+		assert_ok!(Utxo::spend(Origin::signed(0), first));
+		assert_noop!(Utxo::spend(Origin::signed(0), second.clone()), "input already spent this block");
+
+		// In a later block the input is simply gone.
+		System::set_block_number(2);
+		Utxo::on_initialize(2);
+		assert_noop!(Utxo::spend(Origin::signed(0), second), "input utxo not found");
+	});
+}
+
+#[test]
+fn fee_rate_matches_hand_computed_value() {
+	new_test_ext().execute_with(|| {
+		let mut tx = transfer(genesis_out_point(), 40, key_of(&bob()));
+		sign(&mut tx, &alice());
+
+		// 98 bytes of input, 64 of output, 8 of nonce and 1 of priority hint, paying a fee of 60.
+		assert_eq!(tx.encode().len(), 171);
+		assert_eq!(Utxo::fee_rate(&tx), Ok(60 * 1000 / 171));
+		let priority = Utxo::validate_transaction(&tx).unwrap().priority;
+		assert_eq!(priority, Utxo::pool_priority(60 * 1000 / 171, 0));
+
+		let missing = transfer(H256::repeat_byte(9), 40, key_of(&bob()));
+		assert_eq!(Utxo::fee_rate(&missing), Err("input utxo not found"));
+	});
+}
+
+fn create_airdrop(recipient: H256, claim_window: u64) -> H256 {
+	let sig = H512::from(alice().sign(&(&b"airdrop"[..], &genesis_out_point(), recipient, claim_window).encode()).0);
+	assert_ok!(Utxo::create_airdrop(Origin::signed(0), genesis_out_point(), recipient, claim_window, sig));
+	BlakeTwo256::hash_of( &(&genesis_out_point(), &Airdrop {
+		value: GENESIS_VALUE,
+		recipient,
+		fallback_key: key_of(&alice()),
+		expires_at: 1 + claim_window,
+	}) )
+}
+
+#[test]
+fn airdrop_is_claimed_within_window() {
+	new_test_ext().execute_with(|| {
+		let airdrop = create_airdrop(key_of(&bob()), 5);
+		assert!(!UtxoStore::contains_key(genesis_out_point()));
+
+		System::set_block_number(3);
+		let sig = H512::from(alice().sign(&(&b"claim"[..], airdrop).encode()).0);
+		assert_noop!(Utxo::claim_airdrop(Origin::signed(0), airdrop, sig), "signature must be valid");
+		let sig = H512::from(bob().sign(&(&b"claim"[..], airdrop).encode()).0);
+		assert_ok!(Utxo::claim_airdrop(Origin::signed(0), airdrop, sig));
+
+		let claimed = BlakeTwo256::hash_of( &(&airdrop, &key_of(&bob())) );
+		assert_eq!(UtxoStore::get(claimed).unwrap().value, GENESIS_VALUE);
+		assert!(Utxo::airdrop(airdrop).is_none());
+
+		// Nothing is refunded once the window passes.
+		Utxo::on_initialize(6);
+		assert!(!UtxoStore::contains_key(BlakeTwo256::hash_of( &(&airdrop, &key_of(&alice())) )));
+	});
+}
+
+#[test]
+fn unclaimed_airdrop_reverts_to_fallback_key() {
+	new_test_ext().execute_with(|| {
+		let airdrop = create_airdrop(key_of(&bob()), 5);
+
+		System::set_block_number(6);
+		Utxo::on_initialize(6);
+		let refunded = BlakeTwo256::hash_of( &(&airdrop, &key_of(&alice())) );
+		let utxo = UtxoStore::get(refunded).unwrap();
+		assert_eq!((utxo.value, utxo.pub_key), (GENESIS_VALUE, key_of(&alice())));
+		assert!(Utxo::airdrop(airdrop).is_none());
+
+		let sig = H512::from(bob().sign(&(&b"claim"[..], airdrop).encode()).0);
+		assert_noop!(Utxo::claim_airdrop(Origin::signed(0), airdrop, sig), "airdrop not found");
+	});
+}
+
+#[test]
+fn expiry_sweep_is_bounded_and_resumes() {
+	new_test_ext().execute_with(|| {
+		set_max_sweep_per_block(2);
+		let values = [20, 21, 22, 23, 14];
+		let split = split_genesis(&values);
+		let airdrops: Vec<_> = (0..values.len() as u64).map( |index| {
+			let out_point = out_point(&split, index);
+			let sig = H512::from(alice().sign(&(&b"airdrop"[..], &out_point, key_of(&bob()), 5u64).encode()).0);
+			assert_ok!(Utxo::create_airdrop(Origin::signed(0), out_point, key_of(&bob()), 5, sig));
+			BlakeTwo256::hash_of( &(&out_point, &Airdrop {
+				value: values[index as usize],
+				recipient: key_of(&bob()),
+				fallback_key: key_of(&alice()),
+				expires_at: 6,
+			}) )
+		}).collect();
+		let pending = || airdrops.iter().filter( |a| Utxo::airdrop(**a).is_some() ).count();
+
+		Utxo::on_initialize(6);
+		assert_eq!(pending(), 3);
+		Utxo::on_initialize(7);
+		assert_eq!(pending(), 1);
+		// The last refund, then the height is finished.
+		Utxo::on_initialize(8);
+		assert_eq!(pending(), 0);
+		assert_eq!(Utxo::balance_of(key_of(&alice())), 100);
+	});
+}
+
+#[test]
+fn strict_balance_requires_explicit_fees() {
+	new_test_ext().execute_with(|| {
+		let mut surplus = transfer(genesis_out_point(), 90, key_of(&bob()));
+		sign(&mut surplus, &alice());
+
+		set_strict_balance(true);
+		assert_noop!(
+			Utxo::spend(Origin::signed(0), surplus.clone()),
+			"input value must equal output value"
+		);
+
+		let mut explicit = transfer(genesis_out_point(), 90, key_of(&bob()));
+		let fee = TransactionOutput { value: 10, pub_key: FEE_ADDRESS, ..explicit.outputs[0].clone() };
+		explicit.outputs.push(fee);
+		sign(&mut explicit, &alice());
+		assert_ok!(Utxo::spend(Origin::signed(0), explicit.clone()));
+		assert_eq!(Utxo::reward_total(), 10);
+		assert!(!UtxoStore::contains_key(out_point(&explicit, 1)));
+	});
+}
+
+#[test]
+fn lenient_balance_accepts_implicit_fees() {
+	new_test_ext().execute_with(|| {
+		let mut surplus = transfer(genesis_out_point(), 90, key_of(&bob()));
+		sign(&mut surplus, &alice());
+		assert_ok!(Utxo::spend(Origin::signed(0), surplus));
+		assert_eq!(Utxo::reward_total(), 10);
+	});
+}
+
+#[test]
+fn block_rewards_are_recorded_and_pruned() {
+	new_test_ext().execute_with(|| {
+		set_reward_history_retention(3);
+		let authorities = [H256::repeat_byte(1), H256::repeat_byte(2)];
+
+		RewardTotal::put(21);
+		Utxo::disperse_reward(&authorities);
+		assert_eq!(Utxo::rewards_in_block(1), vec![(authorities[0], 10), (authorities[1], 10)]);
+
+		for block in 2..=3 {
+			System::set_block_number(block);
+			Utxo::disperse_reward(&authorities);
+		}
+		assert_eq!(Utxo::rewards_in_block(1).len(), 2);
+
+		System::set_block_number(4);
+		Utxo::disperse_reward(&authorities);
+		assert!(Utxo::rewards_in_block(1).is_empty());
+	});
+}
+
+#[test]
+fn zero_reward_block_leaves_no_record() {
+	new_test_ext().execute_with(|| {
+		let authorities = [H256::repeat_byte(1)];
+		Utxo::disperse_reward(&authorities);
+
+		assert!(!BlockRewards::contains_key(1));
+		assert!(System::events().is_empty());
+		assert!(rewards_of(authorities[0]).is_empty());
+	});
+}
+
+#[test]
+fn reward_utxos_are_flagged_coinbase() {
+	new_test_ext().execute_with(|| {
+		let mut tx = transfer(genesis_out_point(), 90, key_of(&bob()));
+		sign(&mut tx, &alice());
+		assert_ok!(Utxo::spend(Origin::signed(0), tx.clone()));
+		assert!(!Utxo::is_coinbase(&out_point(&tx, 0)));
+		assert!(!Utxo::is_coinbase(&genesis_out_point()));
+
+		let authority = key_of(&alice());
+		Utxo::disperse_reward(&[authority]);
+		let (reward, _) = UtxoStore::iter()
+			.find( |(_, utxo)| utxo.pub_key == authority )
+			.unwrap();
+		assert!(Utxo::is_coinbase(&reward));
+
+		// The flag goes with the UTXO once it's spent.
+		let mut spend_reward = transfer(reward, 10, key_of(&bob()));
+		sign(&mut spend_reward, &alice());
+		assert_ok!(Utxo::spend(Origin::signed(0), spend_reward));
+		assert!(!Utxo::is_coinbase(&reward));
+	});
+}
+
+/// Disperse `value` to `authority` in the current block and return the reward's out point.
+fn mint_reward(authority: H256, value: Value) -> H256 {
+	RewardTotal::put(value);
+	Utxo::disperse_reward(&[authority]);
+	BlakeTwo256::hash_of( &(&TransactionOutput {
+		value,
+		pub_key: authority,
+		lock_height: 0,
+		asset_id: NATIVE_ASSET,
+		metadata: Vec::new(),
+		memo: Vec::new(),
+		requires_gov_flag: None,
+	}, System::block_number()) )
+}
+
+#[test]
+fn immature_coinbase_is_rejected_until_maturity() {
+	new_test_ext().execute_with(|| {
+		set_coinbase_maturity(5);
+		let reward = mint_reward(key_of(&alice()), 30);
+		let mut tx = transfer(reward, 30, key_of(&bob()));
+		sign(&mut tx, &alice());
+
+		System::set_block_number(5);
+		assert_noop!(Utxo::spend(Origin::signed(0), tx.clone()), "immature coinbase");
+
+		System::set_block_number(6);
+		assert_ok!(Utxo::spend(Origin::signed(0), tx));
+	});
+}
+
+#[test]
+fn rewards_within_reorg_window_are_rejected() {
+	new_test_ext().execute_with(|| {
+		set_coinbase_maturity(2);
+		set_reward_reorg_window(10);
+		let reward = mint_reward(key_of(&alice()), 30);
+		let mut tx = transfer(reward, 30, key_of(&bob()));
+		sign(&mut tx, &alice());
+
+		// Mature, but still within the window.
+		System::set_block_number(10);
+		assert_noop!(Utxo::spend(Origin::signed(0), tx.clone()), "reward utxo within reorg window");
+
+		// Regular UTXOs aren't affected.
+		let mut regular = transfer(genesis_out_point(), 50, key_of(&bob()));
+		sign(&mut regular, &alice());
+		assert_ok!(Utxo::spend(Origin::signed(0), regular));
+
+		System::set_block_number(11);
+		assert_ok!(Utxo::spend(Origin::signed(0), tx));
+	});
+}
+
+#[test]
+fn spends_record_their_fee_rate() {
+	new_test_ext().execute_with(|| {
+		let split = split_genesis(&[30, 70]);
+		let mut tx = transfer(out_point(&split, 1), 40, key_of(&bob()));
+		sign(&mut tx, &alice());
+		let rate = Utxo::fee_rate(&tx).unwrap();
+		assert_ok!(Utxo::spend(Origin::signed(0), tx));
+
+		// The split paid no fee, so the block median is between it and `rate`.
+		Utxo::record_fee_rates();
+		assert_eq!(Utxo::recent_fee_rates(), vec![rate / 2]);
+		assert_eq!(Utxo::suggested_fee_rate(), rate / 2);
+	});
+}
+
+#[test]
+fn suggested_fee_rate_tracks_recent_median() {
+	new_test_ext().execute_with(|| {
+		assert_eq!(Utxo::suggested_fee_rate(), 0);
+
+		for rates in vec![vec![100, 300, 200], vec![50], vec![], vec![400, 500]].into_iter() {
+			BlockFeeRates::put(rates);
+			Utxo::record_fee_rates();
+		}
+		// The empty block is skipped.
+		assert_eq!(Utxo::recent_fee_rates(), vec![200, 50, 450]);
+		assert_eq!(Utxo::suggested_fee_rate(), 200);
+
+		// Bursts of high fees move the suggestion as old blocks leave the window.
+		for _ in 0..2 {
+			BlockFeeRates::put(vec![1_000]);
+			Utxo::record_fee_rates();
+		}
+		assert_eq!(Utxo::recent_fee_rates(), vec![450, 1_000, 1_000]);
+		assert_eq!(Utxo::suggested_fee_rate(), 1_000);
+	});
+}
+
+#[test]
+fn send_folds_sub_dust_change_into_fee() {
+	new_test_ext().execute_with(|| {
+		set_dust_threshold(10);
+		split_genesis(&[60, 40]);
+
+		// 60 + 40 covers 90 + 5, leaving change of 5 which is below dust.
+		let mut tx = Utxo::send(key_of(&alice()), key_of(&bob()), 90, 5).unwrap();
+		assert_eq!(tx.inputs.len(), 2);
+		assert_eq!(tx.outputs.len(), 1);
+		sign(&mut tx, &alice());
+		assert_eq!(Utxo::validate_transaction(&tx).map(|_| ()), Ok(()));
+		assert_ok!(Utxo::spend(Origin::signed(0), tx));
+		assert_eq!(Utxo::reward_total(), 10);
+
+		assert_eq!(Utxo::send(key_of(&alice()), key_of(&bob()), 1, 0), Err("insufficient funds"));
+	});
+}
+
+#[test]
+fn send_returns_change_above_dust() {
+	new_test_ext().execute_with(|| {
+		set_dust_threshold(10);
+		let tx = Utxo::send(key_of(&alice()), key_of(&bob()), 50, 5).unwrap();
+		assert_eq!(tx.outputs[1].value, 45);
+		assert_eq!(tx.outputs[1].pub_key, key_of(&alice()));
+	});
+}
+
+#[test]
+fn send_skips_unspendable_utxos() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(Utxo::set_frozen(Origin::root(), genesis_out_point(), true));
+		assert_eq!(Utxo::send(key_of(&alice()), key_of(&bob()), 50, 5), Err("insufficient funds"));
+		assert_ok!(Utxo::set_frozen(Origin::root(), genesis_out_point(), false));
+		assert!(Utxo::send(key_of(&alice()), key_of(&bob()), 50, 5).is_ok());
+	});
+}
+
+#[test]
+fn send_can_consolidate_change() {
+	for (consolidate, inputs, growth) in [(false, 1, 1), (true, 2, 0)].iter() {
+		new_test_ext().execute_with(|| {
+			set_consolidate_change(*consolidate);
+			split_genesis(&[60, 20, 10]);
+			let before = UtxoStore::iter().count();
+
+			// 60 covers the payment; with consolidation the 10 is folded into the change.
+			let mut tx = Utxo::send(key_of(&alice()), key_of(&bob()), 50, 0).unwrap();
+			assert_eq!(tx.inputs.len(), *inputs);
+			assert_eq!(tx.outputs.len(), 2);
+			sign(&mut tx, &alice());
+			assert_ok!(Utxo::spend(Origin::signed(0), tx));
+			assert_eq!(UtxoStore::iter().count(), before + growth);
+		});
+	}
+}
+
+#[test]
+fn partial_spend_folds_sub_dust_change_into_fee() {
+	new_test_ext().execute_with(|| {
+		set_dust_threshold(10);
+		let mut tx = Utxo::partial_spend(genesis_out_point(), key_of(&bob()), 92, 1).unwrap();
+		assert_eq!(tx.outputs.len(), 1);
+		sign(&mut tx, &alice());
+		assert_ok!(Utxo::spend(Origin::signed(0), tx));
+		assert_eq!(Utxo::reward_total(), 8);
+	});
+}
+
+#[test]
+fn outpoint_strings_round_trip() {
+	let txid = BlakeTwo256::hash(b"tx");
+	let formatted = format_outpoint(txid, 7);
+	assert_eq!(formatted, format!("{}:7", hex_of(txid)));
+	assert_eq!(parse_outpoint(&formatted), Ok((txid, 7)));
+	assert_eq!(parse_outpoint(&format!("0x{}", formatted)), Ok((txid, 7)));
+
+	assert!(parse_outpoint(&hex_of(txid)).is_err());
+	assert!(parse_outpoint(&format!("{}:x", hex_of(txid))).is_err());
+	assert!(parse_outpoint("abcd:1").is_err());
+}
+
+#[test]
+fn success_event_carries_created_outpoints() {
+	new_test_ext().execute_with(|| {
+		let mut tx = transfer(genesis_out_point(), 50, key_of(&bob()));
+		let burned = TransactionOutput { value: 5, pub_key: BURN_ADDRESS, ..tx.outputs[0].clone() };
+		let change = TransactionOutput { value: 40, pub_key: key_of(&alice()), ..tx.outputs[0].clone() };
+		tx.outputs.push(burned);
+		tx.outputs.push(change);
+		sign(&mut tx, &alice());
+		assert_ok!(Utxo::spend(Origin::signed(0), tx.clone()));
+
+		let txid = Utxo::txid(&tx);
+		assert_eq!(
+			System::events().last().unwrap().event,
+			TestEvent::utxo(Event::TransactionSuccess(tx, vec![(txid, 0), (txid, 2)]))
+		);
+	});
+}
+
+#[test]
+fn transfers_over_the_balance_cap_are_rejected() {
+	new_test_ext().execute_with(|| {
+		set_max_balance_per_key(60);
+		assert_eq!(Utxo::balance_of(key_of(&alice())), GENESIS_VALUE);
+
+		let mut tx = transfer(genesis_out_point(), 70, key_of(&bob()));
+		sign(&mut tx, &alice());
+		assert_noop!(Utxo::spend(Origin::signed(0), tx), "recipient balance would exceed cap");
+
+		// Alice is over the cap from genesis, but may still pay out of it.
+		let mut tx = transfer(genesis_out_point(), 50, key_of(&bob()));
+		tx.outputs.push(TransactionOutput { value: 40, pub_key: key_of(&alice()), ..tx.outputs[0].clone() });
+		sign(&mut tx, &alice());
+		assert_ok!(Utxo::spend(Origin::signed(0), tx.clone()));
+		assert_eq!(Utxo::balance_of(key_of(&bob())), 50);
+		assert_eq!(Utxo::balance_of(key_of(&alice())), 40);
+
+		let change = out_point(&tx, 1);
+		let mut tx = transfer(change, 20, key_of(&bob()));
+		sign(&mut tx, &alice());
+		assert_noop!(Utxo::spend(Origin::signed(0), tx), "recipient balance would exceed cap");
+
+		let mut tx = transfer(change, 10, key_of(&bob()));
+		sign(&mut tx, &alice());
+		assert_ok!(Utxo::spend(Origin::signed(0), tx));
+		assert_eq!(Utxo::balance_of(key_of(&bob())), 60);
+		assert_eq!(Utxo::balance_of(key_of(&alice())), 0);
+	});
+}
+
+fn scanned_balance(pub_key: H256) -> Value {
+	UtxoStore::iter()
+		.filter( |(_, utxo)| utxo.pub_key == pub_key && utxo.asset_id == NATIVE_ASSET )
+		.map( |(_, utxo)| utxo.value )
+		.sum()
+}
+
+#[test]
+fn balance_index_matches_full_scan() {
+	new_test_ext().execute_with(|| {
+		set_reward_merge_window(5);
+		assert_ok!(Utxo::mint_asset(Origin::root(), 7, 500, key_of(&bob())));
+
+		let (mut from, mut to) = (alice(), bob());
+		let mut coin = genesis_out_point();
+		let mut value = GENESIS_VALUE;
+		for block in 2..7 {
+			System::set_block_number(block);
+			let paid = value / 2;
+			let mut tx = transfer(coin, paid, key_of(&to));
+			let change = TransactionOutput { value: value - paid - 1, pub_key: key_of(&from), ..tx.outputs[0].clone() };
+			tx.outputs.push(change);
+			sign(&mut tx, &from);
+			assert_ok!(Utxo::spend(Origin::signed(0), tx.clone()));
+			// Consecutive rewards merge, replacing the previous reward UTXO.
+			Utxo::disperse_reward(&[key_of(&alice())]);
+
+			for key in &[key_of(&alice()), key_of(&bob())] {
+				assert_eq!(Utxo::balance_of(key), scanned_balance(*key));
+			}
+			coin = out_point(&tx, 0);
+			value = paid;
+			std::mem::swap(&mut from, &mut to);
+		}
+		assert_eq!(Utxo::balance_of(key_of(&alice())) + Utxo::balance_of(key_of(&bob())), GENESIS_VALUE);
+	});
+}
+
+fn scanned_utxos(pub_key: H256) -> Vec<H256> {
+	let mut owned: Vec<_> = UtxoStore::iter()
+		.filter( |(_, utxo)| utxo.pub_key == pub_key )
+		.map( |(out_point, _)| out_point )
+		.collect();
+	owned.sort();
+	owned
+}
+
+fn indexed_utxos(pub_key: H256) -> Vec<H256> {
+	let mut owned = Utxo::owner_utxos(pub_key);
+	owned.sort();
+	owned
+}
+
+#[test]
+fn owner_index_follows_spends_and_creations() {
+	new_test_ext().execute_with(|| {
+		assert_eq!(Utxo::owner_utxos(key_of(&alice())), vec![genesis_out_point()]);
+
+		let split = split_genesis(&[30, 70]);
+		let mut tx = transfer(out_point(&split, 1), 40, key_of(&bob()));
+		tx.outputs.push(TransactionOutput { value: 29, pub_key: key_of(&alice()), ..tx.outputs[0].clone() });
+		sign(&mut tx, &alice());
+		assert_ok!(Utxo::spend(Origin::signed(0), tx.clone()));
+		assert_ok!(Utxo::mint_asset(Origin::root(), 7, 500, key_of(&bob())));
+		mint_reward(key_of(&alice()), 5);
+
+		for key in &[key_of(&alice()), key_of(&bob())] {
+			assert_eq!(indexed_utxos(*key), scanned_utxos(*key));
+		}
+		assert_eq!(Utxo::utxos_of(key_of(&alice())).len(), 3);
+		assert_eq!(Utxo::utxos_of(key_of(&bob())).len(), 2);
+
+		// Spent outputs leave the index.
+		let mut tx = transfer(out_point(&tx, 0), 40, key_of(&alice()));
+		sign(&mut tx, &bob());
+		assert_ok!(Utxo::spend(Origin::signed(0), tx));
+		assert_eq!(indexed_utxos(key_of(&bob())).len(), 1);
+	});
+}
+
+#[test]
+fn owners_are_bounded_by_max_utxos() {
+	new_test_ext().execute_with(|| {
+		set_max_utxos_per_owner(2);
+		let mut tx = transfer(genesis_out_point(), 30, key_of(&alice()));
+		tx.outputs.push(TransactionOutput { value: 30, ..tx.outputs[0].clone() });
+		tx.outputs[1].metadata = b"second".to_vec();
+		tx.outputs.push(TransactionOutput { value: 40, ..tx.outputs[0].clone() });
+		sign(&mut tx, &alice());
+		assert_noop!(Utxo::spend(Origin::signed(0), tx), "owner has too many utxos");
+
+		split_genesis(&[30, 70]);
+		assert_noop!(
+			Utxo::mint_asset(Origin::root(), 7, 500, key_of(&alice())),
+			"owner has too many utxos"
+		);
+
+		// A reward to a full owner waits in the reward total.
+		mint_reward(key_of(&alice()), 5);
+		assert_eq!(Utxo::owner_utxos(key_of(&alice())).len(), 2);
+		assert_eq!(Utxo::reward_total(), 5);
+	});
+}
+
+#[test]
+fn consolidation_pays_a_discounted_minimum_fee() {
+	new_test_ext().execute_with(|| {
+		let split = split_genesis(&[30, 70]);
+		set_minimum_fee(10);
+		set_consolidation_discount(Permill::from_percent(50));
+
+		let mut fan_out = transfer(out_point(&split, 0), 12, key_of(&bob()));
+		fan_out.outputs.push(TransactionOutput { value: 12, pub_key: key_of(&alice()), ..fan_out.outputs[0].clone() });
+		sign(&mut fan_out, &alice());
+		assert_noop!(Utxo::spend(Origin::signed(0), fan_out), "fee below minimum");
+
+		let mut consolidation = transfer(out_point(&split, 0), 94, key_of(&alice()));
+		consolidation.inputs.push(TransactionInput { out_point: out_point(&split, 1), ..Default::default() });
+		sign(&mut consolidation, &alice());
+		assert_eq!(Utxo::minimum_fee(&consolidation), 5);
+		assert_ok!(Utxo::spend(Origin::signed(0), consolidation));
+	});
+}
+
+#[test]
+fn minimum_fee_grows_with_net_new_outputs() {
+	new_test_ext().execute_with(|| {
+		set_minimum_fee(10);
+		set_fee_per_new_output(2);
+		assert_eq!(Utxo::required_fee(0), 10);
+		assert_eq!(Utxo::required_fee(1), 12);
+		assert_eq!(Utxo::required_fee(5), 20);
+
+		// Six outputs from one input leaving a fee of 15, enough for one net-new output.
+		let mut fan_out = transfer(genesis_out_point(), 5, key_of(&bob()));
+		let outputs: Vec<_> = (14..19).map( |value| TransactionOutput { value, ..fan_out.outputs[0].clone() } ).collect();
+		fan_out.outputs.extend(outputs);
+		sign(&mut fan_out, &alice());
+		assert_eq!(Utxo::minimum_fee(&fan_out), 20);
+		assert_noop!(Utxo::spend(Origin::signed(0), fan_out), "fee below minimum");
+
+		let mut pair = transfer(genesis_out_point(), 45, key_of(&bob()));
+		pair.outputs.push(TransactionOutput { value: 40, ..pair.outputs[0].clone() });
+		sign(&mut pair, &alice());
+		assert_eq!(Utxo::minimum_fee(&pair), 12);
+		assert_ok!(Utxo::spend(Origin::signed(0), pair));
+	});
+}
+
+#[test]
+fn pre_filter_rejects_blocked_outputs() {
+	new_test_ext().execute_with(|| {
+		let mut tx = transfer(genesis_out_point(), 50, BLOCKED_KEY);
+		sign(&mut tx, &alice());
+		assert_eq!(Utxo::validate_transaction(&tx), Err("output key is blocked"));
+		assert_noop!(Utxo::spend(Origin::signed(0), tx), "output key is blocked");
+
+		let mut tx = transfer(genesis_out_point(), 50, key_of(&bob()));
+		sign(&mut tx, &alice());
+		assert_ok!(Utxo::spend(Origin::signed(0), tx));
+	});
+}
+
+#[test]
+fn post_spend_hook_sees_applied_transactions() {
+	new_test_ext().execute_with(|| {
+		let mut tx = transfer(genesis_out_point(), 50, key_of(&bob()));
+		tx.outputs.push(TransactionOutput { value: 5, pub_key: BURN_ADDRESS, ..tx.outputs[0].clone() });
+		tx.outputs.push(TransactionOutput { value: 40, pub_key: key_of(&alice()), ..tx.outputs[0].clone() });
+		sign(&mut tx, &alice());
+		assert_ok!(Utxo::spend(Origin::signed(0), tx.clone()));
+
+		assert_eq!(
+			applied_transactions(),
+			vec![(Utxo::txid(&tx), vec![genesis_out_point()], vec![out_point(&tx, 0), out_point(&tx, 2)])]
+		);
+
+		// Rejected transactions aren't reported.
+		assert!(Utxo::spend(Origin::signed(0), tx).is_err());
+		assert_eq!(applied_transactions().len(), 1);
+	});
+}
+
+#[test]
+fn carried_rewards_reach_earners_after_authority_change() {
+	new_test_ext().execute_with(|| {
+		set_reward_decay(Permill::from_percent(50));
+		let (old, new) = (H256::repeat_byte(1), H256::repeat_byte(2));
+
+		RewardTotal::put(100);
+		Utxo::disperse_reward(&[old]);
+		assert_eq!(Utxo::reward_total(), 50);
+
+		// The 50 held back was earned by `old`; only the new fees go to `new`.
+		System::set_block_number(2);
+		RewardTotal::mutate( |total| *total += 20 );
+		Utxo::disperse_reward(&[new]);
+		assert_eq!(Utxo::rewards_in_block(2), vec![(old, 50), (new, 10)]);
+		assert_eq!(rewards_of(old), vec![50, 50]);
+		assert_eq!(Utxo::reward_total(), 10);
+
+		// What's held back now is `new`'s.
+		System::set_block_number(3);
+		Utxo::disperse_reward(&[new]);
+		assert_eq!(Utxo::rewards_in_block(3), vec![(new, 5)]);
+		assert!(rewards_of(old).iter().all( |value| *value == 50 ));
+	});
+}
+
+#[test]
+fn encrypted_memo_round_trips_to_recipient_only() {
+	let encrypted = encrypt_memo(key_of(&bob()), b"invoice 42", [7; 32]).unwrap();
+	assert_eq!(encrypted.len(), b"invoice 42".len() + MEMO_OVERHEAD);
+	assert_eq!(decrypt_memo(&bob(), &encrypted), Ok(b"invoice 42".to_vec()));
+	assert!(decrypt_memo(&alice(), &encrypted).is_err());
+
+	let mut tampered = encrypted.clone();
+	tampered[MEMO_OVERHEAD / 2] ^= 1;
+	assert!(decrypt_memo(&bob(), &tampered).is_err());
+}
+
+#[test]
+fn output_memo_is_bounded() {
+	new_test_ext().execute_with(|| {
+		set_max_memo_length(MEMO_OVERHEAD as u32 + 4);
+		let mut tx = transfer(genesis_out_point(), 50, key_of(&bob()));
+		tx.outputs[0].memo = encrypt_memo(key_of(&bob()), b"rent!", [1; 32]).unwrap();
+		sign(&mut tx, &alice());
+		assert_noop!(Utxo::spend(Origin::signed(0), tx), "output memo too long");
+
+		let mut tx = transfer(genesis_out_point(), 50, key_of(&bob()));
+		tx.outputs[0].memo = encrypt_memo(key_of(&bob()), b"rent", [1; 32]).unwrap();
+		sign(&mut tx, &alice());
+		assert_ok!(Utxo::spend(Origin::signed(0), tx.clone()));
+		let stored = Utxo::get_utxo(out_point(&tx, 0)).unwrap().memo;
+		assert_eq!(decrypt_memo(&bob(), &stored), Ok(b"rent".to_vec()));
+	});
+}
+
+#[test]
+fn spend_weight_grows_with_encoded_size() {
+	let short = transfer(genesis_out_point(), 50, key_of(&bob()));
+	let mut long = short.clone();
+	long.outputs[0].metadata = vec![b'x'; 16];
+	assert_eq!(long.encode().len() - short.encode().len(), 16);
+
+	// Same number of inputs and outputs, so only the size component differs.
+	let weight = |tx: &Transaction| Call::<Test>::spend(tx.clone()).get_dispatch_info().weight;
+	assert_eq!(weight(&short), <() as WeightInfo>::spend(1, 1, short.encode().len() as u32));
+	assert_eq!(weight(&long) - weight(&short), 16 * 10);
+}
+
+#[test]
+fn rollback_restores_inputs_and_removes_outputs() {
+	new_test_ext().execute_with(|| {
+		let mut tx = transfer(genesis_out_point(), 50, key_of(&bob()));
+		tx.outputs.push(TransactionOutput { value: 10, pub_key: BURN_ADDRESS, ..tx.outputs[0].clone() });
+		sign(&mut tx, &alice());
+		assert_ok!(Utxo::spend(Origin::signed(0), tx.clone()));
+		assert_eq!((Utxo::reward_total(), Utxo::burned_total()), (40, 10));
+
+		let txid = Utxo::txid(&tx);
+		assert_noop!(Utxo::rollback_transaction(Origin::signed(0), txid), BadOrigin);
+		assert_ok!(Utxo::rollback_transaction(Origin::root(), txid));
+		assert_eq!(Utxo::applied_transaction(txid), None);
+
+		assert_eq!(Utxo::get_utxo(genesis_out_point()), Some(genesis_utxo()));
+		assert!(!UtxoStore::contains_key(out_point(&tx, 0)));
+		assert_eq!(Utxo::balance_of(key_of(&alice())), GENESIS_VALUE);
+		assert_eq!(Utxo::balance_of(key_of(&bob())), 0);
+		assert_eq!(
+			System::events().last().unwrap().event,
+			TestEvent::utxo(Event::TransactionRolledBack(txid))
+		);
+
+		// The fee and the burn are undone, so total supply is unchanged.
+		assert_eq!((Utxo::reward_total(), Utxo::burned_total()), (0, 0));
+		assert_eq!(Utxo::fees_collected(1, 1), Ok(0));
+		let unspent: Value = UtxoStore::iter().map( |(_, utxo)| utxo.value ).sum();
+		assert_eq!(unspent + Utxo::reward_total(), GENESIS_VALUE);
+	});
+}
+
+#[test]
+fn rollback_after_the_block_withholds_the_paid_out_fee() {
+	new_test_ext().execute_with(|| {
+		let authority = H256::repeat_byte(1);
+		let mut tx = transfer(genesis_out_point(), 50, key_of(&bob()));
+		sign(&mut tx, &alice());
+		assert_ok!(Utxo::spend(Origin::signed(0), tx.clone()));
+		Utxo::disperse_reward(&[authority]);
+		assert_eq!(Utxo::balance_of(authority), 50);
+
+		System::set_block_number(2);
+		Utxo::on_initialize(2);
+		assert_ok!(Utxo::rollback_transaction(Origin::root(), Utxo::txid(&tx)));
+		assert_eq!(Utxo::get_utxo(genesis_out_point()), Some(genesis_utxo()));
+		assert_eq!(Utxo::reward_debt(), 50);
+		assert_eq!(Utxo::fees_collected(1, 1), Ok(0));
+		let unspent: Value = UtxoStore::iter().map( |(_, utxo)| utxo.value ).sum();
+		assert_eq!(unspent - Utxo::reward_debt(), GENESIS_VALUE);
+
+		// The next fees repay the debt before anything is paid out.
+		RewardTotal::put(30);
+		Utxo::disperse_reward(&[authority]);
+		assert_eq!(Utxo::reward_debt(), 20);
+		assert_eq!(Utxo::rewards_in_block(2), vec![]);
+		assert_eq!(Utxo::balance_of(authority), 50);
+	});
+}
+
+#[test]
+fn rollback_is_refused_outside_the_window() {
+	new_test_ext().execute_with(|| {
+		set_rollback_window(2);
+		let mut tx = transfer(genesis_out_point(), 50, key_of(&bob()));
+		sign(&mut tx, &alice());
+		assert_ok!(Utxo::spend(Origin::signed(0), tx.clone()));
+		assert!(Utxo::applied_transaction(Utxo::txid(&tx)).is_some());
+
+		System::set_block_number(3);
+		Utxo::on_initialize(3);
+		assert_noop!(
+			Utxo::rollback_transaction(Origin::root(), Utxo::txid(&tx)),
+			"transaction not in the rollback window"
+		);
+	});
+}
+
+#[test]
+fn rollback_restores_the_records_of_spent_utxos() {
+	new_test_ext().execute_with(|| {
+		set_coinbase_maturity(2);
+		let reward = mint_reward(key_of(&alice()), 40);
+		assert_ok!(Utxo::set_frozen(Origin::root(), genesis_out_point(), true));
+
+		System::set_block_number(3);
+		let mut tx = transfer(genesis_out_point(), 140, key_of(&bob()));
+		tx.inputs.push(TransactionInput { out_point: reward, ..Default::default() });
+		sign(&mut tx, &alice());
+		assert_ok!(Utxo::force_spend(Origin::root(), tx.clone()));
+		assert!(!Utxo::is_coinbase(&reward));
+		assert_ok!(Utxo::rollback_transaction(Origin::root(), Utxo::txid(&tx)));
+
+		// The reward keeps its maturity and the frozen input stays frozen.
+		assert!(Utxo::is_coinbase(&reward));
+		let restored = Utxo::get_utxo(reward).unwrap();
+		assert_eq!(Utxo::is_spendable(&reward, &restored, 2, false), Err("immature coinbase"));
+		assert!(Utxo::is_frozen(genesis_out_point()));
+	});
+}
+
+#[test]
+fn runtime_upgrade_migrates_legacy_utxos() {
+	new_test_ext().execute_with(|| {
+		let legacy = LegacyTransactionOutput { value: GENESIS_VALUE, pub_key: key_of(&alice()) };
+		sp_io::storage::set(&UtxoStore::hashed_key_for(genesis_out_point()), &legacy.encode());
+		BalanceIndex::remove(key_of(&alice()));
+		OwnerUtxos::remove(key_of(&alice()));
+		StorageVersion::put(0);
+
+		Utxo::on_runtime_upgrade();
+		assert_eq!(StorageVersion::get(), 1);
+		assert_eq!(Utxo::utxos_of(key_of(&alice())), vec![(genesis_out_point(), genesis_utxo())]);
+		assert_eq!(Utxo::balance_of(key_of(&alice())), GENESIS_VALUE);
+
+		// Upgrading again leaves the migrated UTXOs and indexes alone.
+		Utxo::on_runtime_upgrade();
+		assert_eq!(Utxo::utxos_of(key_of(&alice())), vec![(genesis_out_point(), genesis_utxo())]);
+		assert_eq!(Utxo::balance_of(key_of(&alice())), GENESIS_VALUE);
+	});
+}
+
+#[test]
+fn rollback_is_refused_once_an_output_is_spent() {
+	new_test_ext().execute_with(|| {
+		let mut tx = transfer(genesis_out_point(), 50, key_of(&bob()));
+		sign(&mut tx, &alice());
+		assert_ok!(Utxo::spend(Origin::signed(0), tx.clone()));
+		let mut onward = transfer(out_point(&tx, 0), 50, key_of(&alice()));
+		sign(&mut onward, &bob());
+		assert_ok!(Utxo::spend(Origin::signed(0), onward));
+
+		assert_noop!(
+			Utxo::rollback_transaction(Origin::root(), Utxo::txid(&tx)),
+			"created output already spent"
+		);
+	});
+}
+
+#[test]
+fn small_inputs_can_only_be_consolidated() {
+	new_test_ext().execute_with(|| {
+		let split = split_genesis(&[3, 97]);
+		set_min_input_value(5);
+
+		let mut tx = transfer(out_point(&split, 0), 3, key_of(&bob()));
+		sign(&mut tx, &alice());
+		assert_noop!(
+			Utxo::spend(Origin::signed(0), tx),
+			"input value below minimum, consolidate it first"
+		);
+
+		let mut tx = transfer(out_point(&split, 0), 100, key_of(&alice()));
+		tx.inputs.push(TransactionInput { out_point: out_point(&split, 1), ..Default::default() });
+		sign(&mut tx, &alice());
+		assert_ok!(Utxo::spend(Origin::signed(0), tx));
+	});
+}
+
+#[test]
+fn owned_value_reads_whichever_state_it_runs_against() {
+	// Two states standing in for the same chain at two blocks.
+	let mut before = new_test_ext();
+	let mut after = new_test_ext();
+	after.execute_with(|| {
+		let mut tx = transfer(genesis_out_point(), 30, key_of(&bob()));
+		tx.outputs.push(TransactionOutput { value: 70, pub_key: key_of(&alice()), ..tx.outputs[0].clone() });
+		sign(&mut tx, &alice());
+		assert_ok!(Utxo::spend(Origin::signed(0), tx));
+	});
+
+	before.execute_with(|| {
+		assert_eq!(Utxo::owned_value(key_of(&alice())), GENESIS_VALUE);
+		assert_eq!(Utxo::owned_value(key_of(&bob())), 0);
+	});
+	after.execute_with(|| {
+		assert_eq!(Utxo::owned_value(key_of(&alice())), 70);
+		assert_eq!(Utxo::owned_value(key_of(&bob())), 30);
+		assert_eq!(Utxo::owned_value(key_of(&bob())), Utxo::balance_of(key_of(&bob())));
+	});
+}
+
+#[test]
+fn implicit_fees_above_the_sanity_limit_are_rejected() {
+	new_test_ext().execute_with(|| {
+		set_max_fee(10);
+
+		// Forgetting the change output would give away 90.
+		let mut tx = transfer(genesis_out_point(), 10, key_of(&bob()));
+		sign(&mut tx, &alice());
+		assert_noop!(Utxo::spend(Origin::signed(0), tx), "fee exceeds sanity limit");
+
+		let mut tx = transfer(genesis_out_point(), 10, key_of(&bob()));
+		tx.outputs.push(TransactionOutput { value: 85, pub_key: key_of(&alice()), ..tx.outputs[0].clone() });
+		sign(&mut tx, &alice());
+		assert_ok!(Utxo::spend(Origin::signed(0), tx));
+		assert_eq!(Utxo::reward_total(), 5);
+	});
+}
+
+fn authorize_session(owner: &sp_core::sr25519::Pair, session_key: H256, expiry: u64) -> DispatchResult {
+	let cold_sig = H512::from(owner.sign(&(&b"session"[..], &session_key, expiry).encode()).0);
+	Utxo::authorize_session(Origin::signed(0), key_of(owner), session_key, expiry, cold_sig)
+}
+
+#[test]
+fn session_key_spends_until_it_expires() {
+	new_test_ext().execute_with(|| {
+		let mut tx = transfer(genesis_out_point(), 50, key_of(&bob()));
+		sign(&mut tx, &bob());
+		assert_noop!(Utxo::spend(Origin::signed(0), tx.clone()), "signature must be valid");
+
+		assert_ok!(authorize_session(&alice(), key_of(&bob()), 5));
+		assert_eq!(Utxo::session_key(key_of(&alice())), Some((key_of(&bob()), 5)));
+		assert_noop!(
+			authorize_session(&alice(), key_of(&bob()), 5),
+			"session must expire after the current one"
+		);
+
+		System::set_block_number(6);
+		assert_noop!(Utxo::spend(Origin::signed(0), tx.clone()), "signature must be valid");
+
+		System::set_block_number(5);
+		assert_ok!(Utxo::spend(Origin::signed(0), tx));
+	});
+}
+
+#[test]
+fn session_authorization_needs_the_owner_signature() {
+	new_test_ext().execute_with(|| {
+		let forged = H512::from(bob().sign(&(&b"session"[..], &key_of(&bob()), 5u64).encode()).0);
+		assert_noop!(
+			Utxo::authorize_session(Origin::signed(0), key_of(&alice()), key_of(&bob()), 5, forged),
+			"signature must be valid"
+		);
+	});
+}
+
+#[test]
+fn revoked_session_key_stops_signing() {
+	new_test_ext().execute_with(|| {
+		let mut tx = transfer(genesis_out_point(), 50, key_of(&bob()));
+		sign(&mut tx, &bob());
+		let revoke = |pair: &sp_core::sr25519::Pair, expiry: u64| {
+			H512::from(pair.sign(&(&b"revoke"[..], &key_of(&bob()), expiry).encode()).0)
+		};
+		assert_noop!(
+			Utxo::revoke_session(Origin::signed(0), key_of(&alice()), revoke(&alice(), 5)),
+			"no session key to revoke"
+		);
+		assert_ok!(authorize_session(&alice(), key_of(&bob()), 5));
+
+		assert_noop!(
+			Utxo::revoke_session(Origin::signed(0), key_of(&alice()), revoke(&bob(), 5)),
+			"signature must be valid"
+		);
+		assert_ok!(Utxo::revoke_session(Origin::signed(0), key_of(&alice()), revoke(&alice(), 5)));
+		assert_eq!(Utxo::session_key(key_of(&alice())), None);
+		assert_noop!(Utxo::spend(Origin::signed(0), tx.clone()), "signature must be valid");
+
+		// Neither the authorization nor the revocation can be replayed.
+		assert_noop!(
+			authorize_session(&alice(), key_of(&bob()), 5),
+			"session must expire after the revoked one"
+		);
+		assert_ok!(authorize_session(&alice(), key_of(&bob()), 6));
+		assert_noop!(
+			Utxo::revoke_session(Origin::signed(0), key_of(&alice()), revoke(&alice(), 5)),
+			"signature must be valid"
+		);
+		assert_ok!(Utxo::spend(Origin::signed(0), tx));
+	});
+}
+
+#[test]
+fn rewards_pool_into_one_utxo_per_epoch() {
+	new_test_ext().execute_with(|| {
+		set_reward_epoch_length(4);
+		let authority = H256::repeat_byte(1);
+
+		for block in 4..8 {
+			System::set_block_number(block);
+			RewardTotal::put(10);
+			Utxo::disperse_reward(&[authority]);
+			assert_eq!(rewards_of(authority), vec![10 * (block as Value - 3)]);
+		}
+		assert_eq!(Utxo::owner_utxos(authority).len(), 1);
+
+		// The next epoch starts a new pool.
+		System::set_block_number(8);
+		RewardTotal::put(10);
+		Utxo::disperse_reward(&[authority]);
+		let mut rewards = rewards_of(authority);
+		rewards.sort();
+		assert_eq!(rewards, vec![10, 40]);
+	});
+}
+
+#[test]
+fn reserves_sum_balances_of_distinct_keys() {
+	new_test_ext().execute_with(|| {
+		let mut tx = transfer(genesis_out_point(), 30, key_of(&bob()));
+		tx.outputs.push(TransactionOutput { value: 60, pub_key: key_of(&alice()), ..tx.outputs[0].clone() });
+		sign(&mut tx, &alice());
+		assert_ok!(Utxo::spend(Origin::signed(0), tx));
+		assert_ok!(Utxo::mint_asset(Origin::root(), 7, 500, key_of(&bob())));
+
+		let keys = [key_of(&alice()), key_of(&bob()), H256::repeat_byte(3), key_of(&bob())];
+		assert_eq!(Utxo::proof_of_reserves(&keys), 90);
+
+		let challenge = b"reserves at block 1".to_vec();
+		let message = (&b"reserves"[..], &challenge).encode();
+		let attest = |pair: &sp_core::sr25519::Pair| (key_of(pair), H512::from(pair.sign(&message).0));
+		assert_eq!(Utxo::attested_reserves(&challenge, &[attest(&alice()), attest(&bob())]), Ok(90));
+
+		// A signature over the bare challenge, made for some other purpose, proves nothing.
+		let bare = (key_of(&alice()), H512::from(alice().sign(&challenge).0));
+		assert_eq!(Utxo::attested_reserves(&challenge, &[bare]), Err("signature must be valid"));
+
+		let forged = (key_of(&bob()), attest(&alice()).1);
+		assert_eq!(
+			Utxo::attested_reserves(&challenge, &[attest(&alice()), forged]),
+			Err("signature must be valid")
+		);
+	});
+}
+
+#[test]
+fn only_canonical_transaction_encodings_decode() {
+	let mut tx = transfer(genesis_out_point(), 50, key_of(&bob()));
+	tx.outputs[0].metadata = b"hi".to_vec();
+	let encoded = tx.encode();
+	assert_eq!(decode_canonical_transaction(&encoded), Ok(tx.clone()));
+
+	// The input count, 1, padded to the two-byte compact form.
+	let mut padded = vec![0b0000_0101, 0];
+	padded.extend_from_slice(&encoded[1..]);
+	assert!(decode_canonical_transaction(&padded).is_err());
+
+	// The metadata length, 2, padded to the four-byte compact form.
+	let metadata_prefix = encoded.len() - 1 - 8 - 1 - 1 - 2 - 1;
+	assert_eq!(encoded[metadata_prefix], 2 << 2);
+	let mut padded = encoded[..metadata_prefix].to_vec();
+	padded.extend_from_slice(&[(2 << 2) | 0b10, 0, 0, 0]);
+	padded.extend_from_slice(&encoded[metadata_prefix + 1..]);
+	assert!(decode_canonical_transaction(&padded).is_err());
+
+	let mut trailing = encoded.clone();
+	trailing.push(0);
+	assert_eq!(decode_canonical_transaction(&trailing), Err("trailing bytes after transaction"));
+	assert!(decode_canonical_transaction(&encoded[..encoded.len() - 1]).is_err());
+}
+
+fn stake_sigs(pair: &sp_core::sr25519::Pair, action: &[u8], out_points: &[H256]) -> Vec<H512> {
+	out_points.iter().map( |out_point| H512::from(pair.sign(&(action, out_point).encode()).0) ).collect()
+}
+
+#[test]
+fn bonded_stake_weights_rewards_and_unbonds_after_cooldown() {
+	new_test_ext().execute_with(|| {
+		let (a, b) = (key_of(&alice()), key_of(&bob()));
+		let mut tx = transfer(genesis_out_point(), 30, a);
+		tx.outputs.push(TransactionOutput { value: 10, pub_key: b, ..tx.outputs[0].clone() });
+		tx.outputs.push(TransactionOutput { value: 60, pub_key: a, ..tx.outputs[0].clone() });
+		sign(&mut tx, &alice());
+		assert_ok!(Utxo::spend(Origin::signed(0), tx.clone()));
+		let (alice_coin, bob_coin) = (out_point(&tx, 0), out_point(&tx, 1));
+
+		RewardTotal::put(100);
+		Utxo::disperse_reward(&[a, b]);
+		assert_eq!(Utxo::rewards_in_block(1), vec![(a, 50), (b, 50)]);
+
+		let sigs = stake_sigs(&alice(), b"bond", &[alice_coin]);
+		assert_ok!(Utxo::bond(Origin::signed(0), vec![alice_coin], sigs));
+		let sigs = stake_sigs(&bob(), b"bond", &[bob_coin]);
+		assert_ok!(Utxo::bond(Origin::signed(0), vec![bob_coin], sigs));
+		assert_eq!(Utxo::stake_of(a), 30);
+		assert!(!UtxoStore::contains_key(alice_coin));
+
+		System::set_block_number(2);
+		RewardTotal::put(100);
+		Utxo::disperse_reward(&[a, b]);
+		assert_eq!(Utxo::rewards_in_block(2), vec![(a, 75), (b, 25)]);
+
+		let sigs = stake_sigs(&alice(), b"unbond", &[alice_coin]);
+		assert_ok!(Utxo::unbond(Origin::signed(0), vec![alice_coin], sigs));
+		assert_eq!(Utxo::stake_of(a), 0);
+		System::set_block_number(4);
+		assert_noop!(
+			Utxo::withdraw_unbonded(Origin::signed(0), vec![alice_coin]),
+			"unbonding period has not passed"
+		);
+
+		System::set_block_number(5);
+		assert_noop!(
+			Utxo::withdraw_unbonded(Origin::signed(0), vec![alice_coin, alice_coin]),
+			"each utxo must only be withdrawn once"
+		);
+		assert_ok!(Utxo::withdraw_unbonded(Origin::signed(0), vec![alice_coin]));
+		// The change, both rewards and the withdrawn coin, counted once.
+		assert_eq!(Utxo::balance_of(a), 60 + 50 + 75 + 30);
+		let withdrawn = Utxo::withdrawn_out_point(&alice_coin);
+		assert_eq!(Utxo::get_utxo(alice_coin), None);
+		assert_eq!(Utxo::get_utxo(withdrawn), Some(tx.outputs[0].clone()));
+		assert_eq!(Utxo::locked_utxo(alice_coin), None);
+		assert!(Utxo::locked_utxo(bob_coin).is_some());
+	});
+}
+
+#[test]
+fn withdrawn_stake_cannot_be_rebonded_with_old_signatures() {
+	new_test_ext().execute_with(|| {
+		let coin = genesis_out_point();
+		let bond_sigs = stake_sigs(&alice(), b"bond", &[coin]);
+		let unbond_sigs = stake_sigs(&alice(), b"unbond", &[coin]);
+		assert_ok!(Utxo::bond(Origin::signed(0), vec![coin], bond_sigs.clone()));
+		assert_ok!(Utxo::unbond(Origin::signed(0), vec![coin], unbond_sigs.clone()));
+		System::set_block_number(4);
+		assert_ok!(Utxo::withdraw_unbonded(Origin::signed(0), vec![coin]));
+
+		// Replaying the signatures no longer finds the coin, at either out point.
+		assert_noop!(Utxo::bond(Origin::signed(0), vec![coin], bond_sigs.clone()), "input utxo not found");
+		let withdrawn = Utxo::withdrawn_out_point(&coin);
+		assert_noop!(Utxo::bond(Origin::signed(0), vec![withdrawn], bond_sigs), "signature must be valid");
+		assert_noop!(Utxo::unbond(Origin::signed(0), vec![coin], unbond_sigs), "bonded utxo not found");
+		assert_eq!(Utxo::get_utxo(withdrawn), Some(genesis_utxo()));
+		assert_eq!(Utxo::stake_of(key_of(&alice())), 0);
+	});
+}
+
+fn reward_high_warnings() -> usize {
+	System::events().iter()
+		.filter( |r| matches!(r.event, TestEvent::utxo(Event::RewardTotalHigh(_))) )
+		.count()
+}
+
+#[test]
+fn reward_total_high_is_emitted_once_per_crossing() {
+	new_test_ext().execute_with(|| {
+		set_reward_total_high_water(15);
+		let values = [23, 24, 25, 28];
+		let split = split_genesis(&values);
+
+		// Fees of 10 each take the total to 10, 20 and 30.
+		for index in 0..3 {
+			let mut tx = transfer(out_point(&split, index as u64), values[index] - 10, key_of(&bob()));
+			sign(&mut tx, &alice());
+			assert_ok!(Utxo::spend(Origin::signed(0), tx));
+		}
+		assert_eq!(reward_high_warnings(), 1);
+		assert!(System::events().iter().any( |r| r.event == TestEvent::utxo(Event::RewardTotalHigh(20)) ));
+
+		// Dispersal brings it back down, so the next crossing warns again.
+		Utxo::disperse_reward(&[H256::repeat_byte(1)]);
+		let mut tx = transfer(out_point(&split, 3), 5, key_of(&bob()));
+		sign(&mut tx, &alice());
+		assert_ok!(Utxo::spend(Origin::signed(0), tx));
+		assert_eq!(reward_high_warnings(), 2);
+	});
+}
+
+#[test]
+fn nonces_distinguish_otherwise_identical_transactions() {
+	new_test_ext().execute_with(|| {
+		let mut first = transfer(genesis_out_point(), 50, key_of(&bob()));
+		let mut second = Transaction { nonce: 1, ..first.clone() };
+		sign(&mut first, &alice());
+		assert_ne!(Utxo::txid(&first), Utxo::txid(&Transaction { nonce: 1, ..first.clone() }));
+		assert_ne!(out_point(&first, 0), out_point(&Transaction { nonce: 1, ..first.clone() }, 0));
+
+		assert_ok!(Utxo::spend(Origin::signed(0), first.clone()));
+		assert_ok!(Utxo::rollback_transaction(Origin::root(), Utxo::txid(&first)));
+
+		// The first signature doesn't cover the second nonce.
+		let replayed = Transaction { nonce: 1, ..first.clone() };
+		assert_noop!(Utxo::spend(Origin::signed(0), replayed), "signature must be valid");
+
+		sign(&mut second, &alice());
+		assert_ok!(Utxo::spend(Origin::signed(0), second.clone()));
+		assert_eq!(Utxo::get_utxo(out_point(&second, 0)).unwrap().value, 50);
+	});
+}
+
+#[test]
+fn gated_outputs_wait_for_their_governance_flag() {
+	new_test_ext().execute_with(|| {
+		let flag = BlakeTwo256::hash(b"launch");
+		let mut tx = transfer(genesis_out_point(), 100, key_of(&bob()));
+		tx.outputs[0].requires_gov_flag = Some(flag);
+		sign(&mut tx, &alice());
+		assert_ok!(Utxo::spend(Origin::signed(0), tx.clone()));
+
+		let mut onward = transfer(out_point(&tx, 0), 100, key_of(&alice()));
+		sign(&mut onward, &bob());
+		assert_noop!(
+			Utxo::spend(Origin::signed(0), onward.clone()),
+			"input utxo awaits a governance flag"
+		);
+
+		assert_noop!(Utxo::set_gov_flag(Origin::signed(0), flag, true), BadOrigin);
+		assert_ok!(Utxo::set_gov_flag(Origin::root(), flag, true));
+		assert!(Utxo::gov_flag(flag));
+		assert_ok!(Utxo::spend(Origin::signed(0), onward));
+	});
+}
+
+/// Run `test` with alice owning a genesis UTXO gated on an unset governance flag, passing its
+/// out point.
+fn with_gated_genesis(test: impl FnOnce(H256)) {
+	let gated = TransactionOutput { requires_gov_flag: Some(BlakeTwo256::hash(b"launch")), ..genesis_utxo() };
+	new_test_ext_with(GenesisConfig {
+		genesis_utxos: vec![gated.clone()],
+		total_genesis_supply: GENESIS_VALUE,
+		..Default::default()
+	}).execute_with(|| test(BlakeTwo256::hash_of(&gated)));
+}
+
+fn owner_sig(message: impl Encode) -> H512 {
+	H512::from(alice().sign(&message.encode()).0)
+}
+
+#[test]
+fn gated_outputs_cannot_become_vouchers() {
+	with_gated_genesis( |gated| {
+		let secret_hash = BlakeTwo256::hash(b"secret");
+		let sig = owner_sig((&b"voucher"[..], &gated, secret_hash));
+		assert_noop!(
+			Utxo::create_voucher(Origin::signed(0), gated, secret_hash, sig),
+			"input utxo awaits a governance flag"
+		);
+	});
+}
+
+#[test]
+fn gated_outputs_cannot_become_airdrops() {
+	with_gated_genesis( |gated| {
+		let sig = owner_sig((&b"airdrop"[..], &gated, key_of(&bob()), 5u64));
+		assert_noop!(
+			Utxo::create_airdrop(Origin::signed(0), gated, key_of(&bob()), 5, sig),
+			"input utxo awaits a governance flag"
+		);
+	});
+}
+
+#[test]
+fn gated_outputs_cannot_be_guarded() {
+	with_gated_genesis( |gated| {
+		let sig = owner_sig((&b"guard"[..], &gated, key_of(&bob())));
+		assert_noop!(
+			Utxo::guard(Origin::signed(0), gated, key_of(&bob()), sig),
+			"input utxo awaits a governance flag"
+		);
+	});
+}
+
+#[test]
+fn gated_outputs_cannot_be_bonded() {
+	with_gated_genesis( |gated| {
+		let sigs = stake_sigs(&alice(), b"bond", &[gated]);
+		assert_noop!(Utxo::bond(Origin::signed(0), vec![gated], sigs), "input utxo awaits a governance flag");
+	});
+}
+
+#[test]
+fn one_bad_signature_fails_the_batch() {
+	new_test_ext().execute_with(|| {
+		set_batch_verify_threshold(2);
+		let split = split_genesis(&[30, 30, 40]);
+		let mut tx = transfer(out_point(&split, 0), 60, key_of(&bob()));
+		tx.inputs.push(TransactionInput { out_point: out_point(&split, 1), ..Default::default() });
+		sign(&mut tx, &alice());
+
+		let mut forged = tx.clone();
+		forged.inputs[1].sig_script = H512::from(bob().sign(&forged.encode()).0);
+		assert_noop!(Utxo::spend(Origin::signed(0), forged), "input signatures must be valid");
+		assert_ok!(Utxo::spend(Origin::signed(0), tx));
+
+		// A single input stays below the threshold and gets the precise error.
+		let unsigned = transfer(out_point(&split, 2), 40, key_of(&bob()));
+		assert_noop!(Utxo::spend(Origin::signed(0), unsigned), "signature must be valid");
+	});
+}
+
+#[test]
+fn batched_inputs_verify_inside_block_import_batch() {
+	new_test_ext().execute_with(|| {
+		set_batch_verify_threshold(2);
+		let split = split_genesis(&[30, 30, 40]);
+		let mut tx = transfer(out_point(&split, 0), 60, key_of(&bob()));
+		tx.inputs.push(TransactionInput { out_point: out_point(&split, 1), ..Default::default() });
+		sign(&mut tx, &alice());
+		let mut forged = tx.clone();
+		forged.inputs[1].sig_script = H512::from(bob().sign(&forged.encode()).0);
+
+		// `Executive::execute_block` holds a signature batch open around the block's extrinsics.
+		sp_io::crypto::start_batch_verify();
+		assert_noop!(Utxo::spend(Origin::signed(0), forged), "input signatures must be valid");
+		assert_ok!(Utxo::spend(Origin::signed(0), tx));
+		assert!(sp_io::crypto::finish_batch_verify());
+	});
+}
+
+#[test]
+fn every_burn_address_counts_toward_burned_total() {
+	new_test_ext().execute_with(|| {
+		let dead = H256::repeat_byte(0xde);
+		let eaten = H256::from_low_u64_be(0xdead);
+		assert_noop!(Utxo::set_burn_address(Origin::signed(0), dead, true), BadOrigin);
+		assert_ok!(Utxo::set_burn_address(Origin::root(), dead, true));
+		assert_ok!(Utxo::set_burn_address(Origin::root(), eaten, true));
+		assert_noop!(
+			Utxo::set_burn_address(Origin::root(), BURN_ADDRESS, false),
+			"the default burn address is fixed"
+		);
+
+		let mut tx = transfer(genesis_out_point(), 78, key_of(&bob()));
+		for (value, pub_key) in vec![(5, BURN_ADDRESS), (10, dead), (7, eaten)] {
+			tx.outputs.push(TransactionOutput { value, pub_key, ..tx.outputs[0].clone() });
+		}
+		sign(&mut tx, &alice());
+		assert_ok!(Utxo::spend(Origin::signed(0), tx.clone()));
+		assert_eq!(Utxo::burned_total(), 22);
+		assert!((1..4).all( |index| !UtxoStore::contains_key(out_point(&tx, index)) ));
+
+		// UTXOs a key owned before it became a burn address are stuck.
+		assert_ok!(Utxo::set_burn_address(Origin::root(), key_of(&bob()), true));
+		let mut onward = transfer(out_point(&tx, 0), 78, key_of(&alice()));
+		sign(&mut onward, &bob());
+		assert_noop!(Utxo::spend(Origin::signed(0), onward), "can't spend from a burn address");
+	});
+}
+
+#[test]
+fn fees_collected_sums_block_fees_within_history() {
+	new_test_ext().execute_with(|| {
+		set_reward_history_retention(3);
+		let authorities = [H256::repeat_byte(1)];
+		let values = [20, 25, 25, 30];
+		let split = split_genesis(&values);
+		let block_fees = [vec![10], vec![5, 3], vec![7]];
+
+		let mut index = 0;
+		for (block, fees) in block_fees.iter().enumerate() {
+			System::set_block_number(block as u64 + 1);
+			for fee in fees {
+				let mut tx = transfer(out_point(&split, index), values[index as usize] - fee, key_of(&bob()));
+				sign(&mut tx, &alice());
+				assert_ok!(Utxo::spend(Origin::signed(0), tx));
+				index += 1;
+			}
+			Utxo::disperse_reward(&authorities);
+		}
+
+		for (block, fees) in block_fees.iter().enumerate() {
+			let block = block as u64 + 1;
+			assert_eq!(Utxo::fees_collected(block, block), Ok(fees.iter().sum()));
+		}
+		assert_eq!(Utxo::fees_collected(1, 3), Ok(25));
+		assert_eq!(Utxo::fees_collected(3, 2), Err("empty block range"));
+		assert_eq!(Utxo::fees_collected(1, 4), Err("block range ends in the future"));
+
+		System::set_block_number(4);
+		Utxo::disperse_reward(&authorities);
+		assert_eq!(Utxo::fees_collected(1, 4), Err("block range has been pruned"));
+		assert_eq!(Utxo::fees_collected(2, 4), Ok(15));
+	});
+}
+
+#[test]
+fn genesis_accepts_matching_native_supply() {
+	new_test_ext_with(GenesisConfig {
+		genesis_utxos: vec![genesis_utxo()],
+		initial_reward: 20,
+		total_genesis_supply: GENESIS_VALUE + 20,
+		..Default::default()
+	}).execute_with(|| {
+		assert_eq!(Utxo::reward_total(), 20);
+	});
+}
+
+#[test]
+#[should_panic(expected = "genesis native outputs and initial reward total 120 but declared supply is 100")]
+fn genesis_rejects_mismatched_native_supply() {
+	new_test_ext_with(GenesisConfig {
+		genesis_utxos: vec![genesis_utxo()],
+		initial_reward: 20,
+		total_genesis_supply: GENESIS_VALUE,
+		..Default::default()
+	});
+}
+
+#[test]
+fn idle_reward_dust_is_swept_after_the_idle_period() {
+	new_test_ext().execute_with(|| {
+		let authorities = [H256::repeat_byte(1), H256::repeat_byte(2), H256::repeat_byte(3)];
+		assert_noop!(Utxo::sweep_reward_dust(Origin::signed(0)), "no reward dust to sweep");
+
+		// Two units can't be split three ways, so they sit in `RewardTotal`.
+		RewardTotal::put(2);
+		Utxo::disperse_reward(&authorities);
+		assert_eq!(Utxo::reward_total(), 2);
+
+		for block in 2..=5 {
+			System::set_block_number(block);
+			Utxo::disperse_reward(&authorities);
+			assert_noop!(
+				Utxo::sweep_reward_dust(Origin::signed(0)),
+				"reward dust hasn't been idle long enough"
+			);
+		}
+
+		System::set_block_number(6);
+		assert_ok!(Utxo::sweep_reward_dust(Origin::signed(0)));
+		assert_eq!(Utxo::reward_total(), 0);
+		assert_eq!(Utxo::balance_of(H256::repeat_byte(0x7e)), 2);
+		assert_noop!(Utxo::sweep_reward_dust(Origin::signed(0)), "no reward dust to sweep");
+	});
+}
+
+#[test]
+fn sweeping_reward_dust_leaves_this_blocks_fees() {
+	new_test_ext().execute_with(|| {
+		let authorities = [H256::repeat_byte(1), H256::repeat_byte(2), H256::repeat_byte(3)];
+		RewardTotal::put(2);
+		Utxo::disperse_reward(&authorities);
+
+		System::set_block_number(6);
+		let mut tx = transfer(genesis_out_point(), 70, key_of(&bob()));
+		sign(&mut tx, &alice());
+		assert_ok!(Utxo::spend(Origin::signed(0), tx));
+		assert_eq!(Utxo::reward_total(), 32);
+
+		// Only the carried dust is swept; the block's fee is dispersed as usual.
+		assert_ok!(Utxo::sweep_reward_dust(Origin::signed(0)));
+		assert_eq!(Utxo::balance_of(H256::repeat_byte(0x7e)), 2);
+		assert_eq!(Utxo::reward_total(), 30);
+		Utxo::disperse_reward(&authorities);
+		assert_eq!(Utxo::rewards_in_block(6), authorities.iter().map( |a| (*a, 10) ).collect::<Vec<_>>());
+	});
+}
+
+#[test]
+fn filled_templates_are_valid_signable_transactions() {
+	new_test_ext().execute_with(|| {
+		let base = genesis_utxo();
+		let template = TransactionTemplate {
+			outputs: vec![
+				OutputTemplate {
+					value: TemplateSlot::Placeholder("amount".into()),
+					pub_key: TemplateSlot::Placeholder("payee".into()),
+					base: base.clone(),
+				},
+				OutputTemplate {
+					value: TemplateSlot::Placeholder("change".into()),
+					pub_key: TemplateSlot::Fixed(key_of(&alice())),
+					base,
+				},
+			],
+		};
+		let split = split_genesis(&[40, 60]);
+		let keys = vec![("payee".to_string(), key_of(&bob()))].into_iter().collect();
+
+		for (index, (amount, change)) in vec![(15, 25), (50, 10)].into_iter().enumerate() {
+			let values = vec![("amount".to_string(), amount), ("change".to_string(), change)]
+				.into_iter().collect();
+			let mut tx = template.fill(&[out_point(&split, index as u64)], 0, &values, &keys).unwrap();
+			assert_eq!((tx.outputs[0].value, tx.outputs[0].pub_key), (amount, key_of(&bob())));
+			sign(&mut tx, &alice());
+			assert_ok!(Utxo::spend(Origin::signed(0), tx.clone()));
+			assert_eq!(Utxo::get_utxo(out_point(&tx, 1)).unwrap().value, change);
+		}
+
+		let missing = template.fill(&[], 0, &Default::default(), &keys);
+		assert_eq!(missing, Err("placeholder `amount` isn't filled".to_string()));
+	});
+}
+
+#[test]
+fn input_errors_follow_documented_precedence() {
+	new_test_ext().execute_with(|| {
+		set_min_input_value(60);
+		let flag = BlakeTwo256::hash(b"launch");
+		let mut tx = transfer(genesis_out_point(), 50, key_of(&bob()));
+		tx.outputs[0].lock_height = 10;
+		tx.outputs[0].requires_gov_flag = Some(flag);
+		sign(&mut tx, &alice());
+		assert_ok!(Utxo::spend(Origin::signed(0), tx.clone()));
+		let locked = out_point(&tx, 0);
+
+		assert_noop!(Utxo::set_frozen(Origin::signed(0), locked, true), BadOrigin);
+		assert_ok!(Utxo::set_frozen(Origin::root(), locked, true));
+		let mut onward = transfer(locked, 50, key_of(&alice()));
+		sign(&mut onward, &bob());
+
+		// Frozen, awaiting a flag, time-locked and below the minimum: each fix reveals the next.
+		assert_noop!(Utxo::spend(Origin::signed(0), onward.clone()), "input utxo is frozen");
+		assert_ok!(Utxo::set_frozen(Origin::root(), locked, false));
+		assert_noop!(
+			Utxo::spend(Origin::signed(0), onward.clone()),
+			"input utxo awaits a governance flag"
+		);
+		assert_ok!(Utxo::set_gov_flag(Origin::root(), flag, true));
+		assert_noop!(Utxo::spend(Origin::signed(0), onward.clone()), "input utxo is time-locked");
+		System::set_block_number(10);
+		assert_noop!(
+			Utxo::spend(Origin::signed(0), onward.clone()),
+			"input value below minimum, consolidate it first"
+		);
+		set_min_input_value(0);
+		assert_ok!(Utxo::spend(Origin::signed(0), onward));
+	});
+}
+
+#[test]
+fn frozen_utxos_cannot_be_moved_by_other_calls() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(Utxo::set_frozen(Origin::root(), genesis_out_point(), true));
+		let sig = H512::from(alice().sign(&(&b"refresh"[..], &genesis_out_point(), 5u64).encode()).0);
+		assert_noop!(
+			Utxo::refresh(Origin::signed(0), genesis_out_point(), 5, sig),
+			"input utxo is frozen"
+		);
+		let sig = H512::from(alice().sign(&(&b"bond"[..], genesis_out_point()).encode()).0);
+		assert_noop!(
+			Utxo::bond(Origin::signed(0), vec![genesis_out_point()], vec![sig]),
+			"input utxo is frozen"
+		);
+	});
+}
+
+#[test]
+fn compact_wire_format_round_trips_and_is_smaller() {
+	new_test_ext().execute_with(|| {
+		let mut payment = transfer(genesis_out_point(), 60, key_of(&bob()));
+		let second_input = TransactionInput { out_point: H256::repeat_byte(1), ..Default::default() };
+		let change = TransactionOutput { value: 30, pub_key: key_of(&alice()), ..payment.outputs[0].clone() };
+		payment.inputs.push(second_input);
+		payment.outputs.push(change);
+		sign(&mut payment, &alice());
+
+		let mut rich = payment.clone();
+		rich.inputs[1].sig_script = H512::repeat_byte(2);
+		rich.inputs[1].guardian_sig = Some(H512::repeat_byte(4));
+		rich.outputs[1] = TransactionOutput {
+			lock_height: 1_000,
+			asset_id: 7,
+			metadata: b"label".to_vec(),
+			memo: vec![9; 60],
+			requires_gov_flag: Some(H256::repeat_byte(3)),
+			..rich.outputs[1].clone()
+		};
+		rich.nonce = 42;
+		rich.priority_hint = 200;
+
+		for tx in vec![payment.clone(), rich] {
+			let compact = to_compact(&tx);
+			assert_eq!(from_compact(&compact), Ok(tx.clone()));
+			assert!(compact.len() < tx.encode().len());
+		}
+		// Inputs shrink from 195 bytes to 130 by sharing the signature, outputs from 127 to 69 by
+		// compacting values and leaving out defaults, and the nonce from 8 bytes to 1.
+		assert_eq!((payment.encode().len(), to_compact(&payment).len()), (331, 201));
+
+		let mut trailing = to_compact(&payment);
+		trailing.push(0);
+		assert_eq!(from_compact(&trailing), Err("trailing bytes after transaction"));
+		assert_eq!(from_compact(&[2]), Err("invalid compact transaction"));
+	});
+}
+
+#[test]
+fn witness_validation_matches_stateful_validation() {
+	new_test_ext().execute_with(|| {
+		let mut tx = transfer(genesis_out_point(), 90, key_of(&bob()));
+		sign(&mut tx, &alice());
+		let witnesses: std::collections::BTreeMap<_, _> =
+			vec![(genesis_out_point(), genesis_utxo())].into_iter().collect();
+		let stateful = Utxo::validate_transaction(&tx).unwrap();
+		assert_eq!(Utxo::validate_transaction_with_witness(&tx, &witnesses), Ok(stateful));
+
+		// Without a witness the input is required, as if it didn't exist yet.
+		let unwitnessed = Utxo::validate_transaction_with_witness(&tx, &Default::default()).unwrap();
+		assert_eq!(unwitnessed.requires, vec![genesis_out_point().as_fixed_bytes().to_vec()]);
+
+		// Witnesses stand in for the UTXO set, even for outputs it doesn't hold.
+		let out_point = H256::repeat_byte(9);
+		let mut remote = transfer(out_point, 90, key_of(&bob()));
+		sign(&mut remote, &alice());
+		assert_eq!(Utxo::validate_transaction(&remote).unwrap().requires.len(), 1);
+		let witnesses = vec![(out_point, genesis_utxo())].into_iter().collect();
+		assert!(Utxo::validate_transaction_with_witness(&remote, &witnesses).unwrap().requires.is_empty());
+	});
+}
+
+#[test]
+fn signatures_for_one_call_are_rejected_by_another() {
+	new_test_ext().execute_with(|| {
+		let guardian = key_of(&bob());
+		let signed = |tag: &[u8]| H512::from(alice().sign(&(tag, &genesis_out_point(), guardian).encode()).0);
+		let guard_sig = signed(b"guard");
+		assert_noop!(
+			Utxo::create_voucher(Origin::signed(0), genesis_out_point(), guardian, guard_sig),
+			"signature must be valid"
+		);
+
+		assert_noop!(
+			Utxo::guard(Origin::signed(0), genesis_out_point(), guardian, signed(b"voucher")),
+			"signature must be valid"
+		);
+
+		let refreshing = refresh_sig(&alice(), genesis_out_point(), 5);
+		assert_noop!(
+			Utxo::create_airdrop(Origin::signed(0), genesis_out_point(), guardian, 5, refreshing),
+			"signature must be valid"
+		);
+		assert_ok!(Utxo::guard(Origin::signed(0), genesis_out_point(), guardian, guard_sig));
+	});
+}
+
+#[test]
+fn guarded_outputs_need_owner_and_guardian_signatures() {
+	new_test_ext().execute_with(|| {
+		let guardian = bob();
+		let message = (&b"guard"[..], &genesis_out_point(), key_of(&guardian)).encode();
+		let sig = H512::from(alice().sign(&message).0);
+		assert_ok!(Utxo::guard(Origin::signed(0), genesis_out_point(), key_of(&guardian), sig));
+		let guarded = BlakeTwo256::hash_of( &(&genesis_out_point(), &GuardedOutput {
+			value: GENESIS_VALUE,
+			owner: key_of(&alice()),
+			guardian: key_of(&guardian),
+		}) );
+		assert!(!UtxoStore::contains_key(genesis_out_point()));
+		assert!(Utxo::guarded_output(guarded).is_some());
+
+		let mut tx = transfer(guarded, GENESIS_VALUE, H256::repeat_byte(5));
+		let cosign = |pair: &sp_core::sr25519::Pair, tx: &Transaction| {
+			Some(H512::from(pair.sign(&Utxo::get_simple_transaction(tx)).0))
+		};
+		sign(&mut tx, &alice());
+		assert_noop!(Utxo::spend(Origin::signed(0), tx.clone()), "guardian signature missing");
+		tx.inputs[0].guardian_sig = cosign(&alice(), &tx);
+		assert_noop!(Utxo::spend(Origin::signed(0), tx.clone()), "guardian signature must be valid");
+		tx.inputs[0].guardian_sig = cosign(&guardian, &tx);
+
+		let mut owner_unsigned = tx.clone();
+		owner_unsigned.inputs[0].sig_script = H512::zero();
+		assert_noop!(Utxo::spend(Origin::signed(0), owner_unsigned), "signature must be valid");
+
+		assert_ok!(Utxo::spend(Origin::signed(0), tx.clone()));
+		assert!(Utxo::guarded_output(guarded).is_none());
+		assert_eq!(Utxo::balance_of(H256::repeat_byte(5)), GENESIS_VALUE);
+	});
+}
+
+#[test]
+fn new_authorities_wait_for_minimum_uptime_before_sharing_rewards() {
+	new_test_ext().execute_with(|| {
+		set_min_reward_uptime(2);
+		let (old, new) = (H256::repeat_byte(1), H256::repeat_byte(2));
+		// `old` has been in the set long enough by block 3.
+		Utxo::disperse_reward(&[old]);
+
+		for block in 3..=4 {
+			System::set_block_number(block);
+			RewardTotal::put(10);
+			Utxo::disperse_reward(&[old, new]);
+			assert_eq!(Utxo::rewards_in_block(block), vec![(old, 10)]);
+		}
+
+		System::set_block_number(5);
+		RewardTotal::put(10);
+		Utxo::disperse_reward(&[old, new]);
+		assert_eq!(Utxo::rewards_in_block(5), vec![(old, 5), (new, 5)]);
+
+		// Leaving the set resets the wait.
+		System::set_block_number(6);
+		Utxo::disperse_reward(&[old]);
+		System::set_block_number(7);
+		RewardTotal::put(10);
+		Utxo::disperse_reward(&[old, new]);
+		assert_eq!(Utxo::rewards_in_block(7), vec![(old, 10)]);
+	});
+}
+
+#[test]
+fn priority_hints_order_equal_fee_rates_only() {
+	new_test_ext().execute_with(|| {
+		let priority = |paid: Value, hint: u8| {
+			let mut tx = transfer(genesis_out_point(), paid, key_of(&bob()));
+			tx.priority_hint = hint;
+			sign(&mut tx, &alice());
+			Utxo::validate_transaction(&tx).unwrap().priority
+		};
+
+		assert!(priority(90, 200) > priority(90, 10));
+		assert!(priority(90, 10) > priority(90, 0));
+		// A higher fee wins whatever the hints.
+		assert!(priority(89, 0) > priority(90, 255));
+	});
+}
+
+#[test]
+fn largest_utxo_is_the_biggest_spendable_one() {
+	new_test_ext().execute_with(|| {
+		assert_eq!(Utxo::largest_utxo(key_of(&bob())), None);
+		let values = [10, 45, 30, 15];
+		let split = split_genesis(&values);
+		assert_eq!(Utxo::largest_utxo(key_of(&alice())), Some((out_point(&split, 1), 45)));
+
+		// A time-locked UTXO is passed over until it unlocks.
+		let mut locked = transfer(out_point(&split, 0), 55, key_of(&alice()));
+		for index in 2..4 {
+			locked.inputs.push(TransactionInput { out_point: out_point(&split, index), ..Default::default() });
+		}
+		locked.outputs[0].lock_height = 5;
+		sign(&mut locked, &alice());
+		assert_ok!(Utxo::spend(Origin::signed(0), locked.clone()));
+		assert_eq!(Utxo::largest_utxo(key_of(&alice())), Some((out_point(&split, 1), 45)));
+		System::set_block_number(5);
+		assert_eq!(Utxo::largest_utxo(key_of(&alice())), Some((out_point(&locked, 0), 55)));
+	});
+}
+
+#[test]
+fn inputs_available_reports_each_out_point() {
+	new_test_ext().execute_with(|| {
+		let split = split_genesis(&[30, 70]);
+		let mut locked = transfer(out_point(&split, 0), 30, key_of(&bob()));
+		locked.outputs[0].lock_height = 5;
+		sign(&mut locked, &alice());
+		assert_ok!(Utxo::spend(Origin::signed(0), locked.clone()));
+
+		let queried = [out_point(&split, 1), genesis_out_point(), out_point(&locked, 0), H256::zero()];
+		assert_eq!(Utxo::inputs_available(&queried), vec![true, false, false, false]);
+
+		System::set_block_number(5);
+		assert_eq!(Utxo::inputs_available(&queried), vec![true, false, true, false]);
+	});
+}
+
+#[test]
+fn large_reward_shares_are_paid_in_denominations() {
+	new_test_ext().execute_with(|| {
+		set_reward_denomination(250);
+		let authority = H256::repeat_byte(1);
+		RewardTotal::put(1000);
+		Utxo::disperse_reward(&[authority]);
+
+		let mut values: Vec<_> = Utxo::utxos_of(authority).into_iter().map( |(_, utxo)| utxo.value ).collect();
+		values.sort();
+		assert_eq!(values, vec![250, 250, 250, 250]);
+		assert_eq!(Utxo::rewards_in_block(1), vec![(authority, 1000)]);
+
+		// Anything left over gets its own UTXO.
+		System::set_block_number(2);
+		RewardTotal::put(600);
+		Utxo::disperse_reward(&[authority]);
+		assert_eq!(Utxo::utxos_of(authority).len(), 7);
+		assert_eq!(Utxo::balance_of(authority), 1600);
+	});
+}
+
+#[test]
+fn denominated_rewards_fit_the_owner_utxo_cap() {
+	new_test_ext().execute_with(|| {
+		set_reward_denomination(250);
+		set_max_utxos_per_owner(3);
+		let authority = H256::repeat_byte(1);
+		RewardTotal::put(1000);
+		Utxo::disperse_reward(&[authority]);
+
+		// The last UTXO there's room for takes the rest.
+		let mut values: Vec<_> = Utxo::utxos_of(authority).into_iter().map( |(_, utxo)| utxo.value ).collect();
+		values.sort();
+		assert_eq!(values, vec![250, 250, 500]);
+		assert_eq!(Utxo::rewards_in_block(1), vec![(authority, 1000)]);
+		assert_eq!(Utxo::reward_total(), 0);
+
+		// With no room left the share is carried over.
+		System::set_block_number(2);
+		RewardTotal::put(600);
+		Utxo::disperse_reward(&[authority]);
+		assert_eq!(Utxo::reward_total(), 600);
+		assert_eq!(Utxo::balance_of(authority), 1000);
+	});
+}
+
+#[test]
+fn block_body_applies_like_the_runtime() {
+	new_test_ext().execute_with(|| {
+		let mut first = transfer(genesis_out_point(), 60, key_of(&bob()));
+		sign(&mut first, &alice());
+		// Spends an output created earlier in the same block.
+		let mut second = transfer(out_point(&first, 0), 50, key_of(&alice()));
+		sign(&mut second, &bob());
+
+		let mut state = BlockBodyState { height: 1, utxos: UtxoStore::iter().collect(), ..Default::default() };
+		let before = state.clone();
+		assert_eq!(Utxo::apply_block_body(&[second.clone()], &mut state), Err("input utxo not found"));
+		assert_eq!(state, before);
+
+		assert_ok!(Utxo::apply_block_body(&[first.clone(), second.clone()], &mut state));
+		assert_ok!(Utxo::spend(Origin::signed(0), first));
+		assert_ok!(Utxo::spend(Origin::signed(0), second));
+		assert_eq!(state.utxos, UtxoStore::iter().collect());
+	});
+}
+
+#[test]
+fn block_body_spends_a_guarded_output_only_once() {
+	// No externalities: the body is applied to `state` alone.
+	let guarded = H256::repeat_byte(7);
+	let mut state = BlockBodyState { height: 1, ..Default::default() };
+	state.guarded.insert(guarded, GuardedOutput {
+		value: GENESIS_VALUE,
+		owner: key_of(&alice()),
+		guardian: key_of(&bob()),
+	});
+	let spend_guarded = |nonce| {
+		let mut tx = Transaction { nonce, ..transfer(guarded, GENESIS_VALUE, H256::repeat_byte(5)) };
+		sign(&mut tx, &alice());
+		tx.inputs[0].guardian_sig = Some(H512::from(bob().sign(&Utxo::get_simple_transaction(&tx)).0));
+		tx
+	};
+	let (first, again) = (spend_guarded(0), spend_guarded(1));
+
+	let before = state.clone();
+	assert_eq!(
+		Utxo::apply_block_body(&[first.clone(), again], &mut state),
+		Err("input utxo not found")
+	);
+	assert_eq!(state, before);
+
+	assert_ok!(Utxo::apply_block_body(&[first.clone()], &mut state));
+	assert!(state.guarded.is_empty());
+	assert_eq!(state.utxos.get(&out_point(&first, 0)).map( |utxo| utxo.value ), Some(GENESIS_VALUE));
+}
+
+#[test]
+fn block_body_checks_caps_against_its_own_state() {
+	new_test_ext().execute_with(|| {
+		set_max_utxos_per_owner(1);
+		let mut first = transfer(genesis_out_point(), 60, key_of(&bob()));
+		sign(&mut first, &alice());
+		// Bob already got a UTXO earlier in the body.
+		let mut tx = transfer(H256::repeat_byte(9), 10, key_of(&bob()));
+		sign(&mut tx, &alice());
+
+		let mut state = BlockBodyState { height: 1, utxos: UtxoStore::iter().collect(), ..Default::default() };
+		state.utxos.insert(H256::repeat_byte(9), TransactionOutput { value: 10, ..genesis_utxo() });
+		assert_eq!(
+			Utxo::apply_block_body(&[first, tx], &mut state),
+			Err("owner has too many utxos")
+		);
+	});
+}
+
+#[test]
+fn asset_count_per_transaction_is_capped() {
+	let owned = |value, asset_id| {
+		TransactionOutput { pub_key: key_of(&alice()), ..asset_utxo(value, asset_id) }
+	};
+	new_test_ext_with(GenesisConfig {
+		genesis_utxos: vec![genesis_utxo(), owned(10, 1), owned(20, 2)],
+		asset_supplies: vec![(1, 10), (2, 20)].into_iter().collect(),
+		total_genesis_supply: GENESIS_VALUE,
+		..Default::default()
+	}).execute_with(|| {
+		set_max_assets_per_transaction(2);
+		let mut tx = Transaction {
+			inputs: vec![
+				TransactionInput { out_point: genesis_out_point(), ..Default::default() },
+				TransactionInput { out_point: BlakeTwo256::hash_of(&owned(10, 1)), ..Default::default() },
+				TransactionInput { out_point: BlakeTwo256::hash_of(&owned(20, 2)), ..Default::default() },
+			],
+			outputs: vec![
+				TransactionOutput { pub_key: key_of(&bob()), ..genesis_utxo() },
+				asset_utxo(10, 1),
+				asset_utxo(20, 2),
+			],
+			nonce: 0,
+			priority_hint: 0,
+		};
+		sign(&mut tx, &alice());
+		assert_noop!(Utxo::spend(Origin::signed(0), tx.clone()), "too many assets in transaction");
+
+		// Native and one other asset are within the cap.
+		let mut two_assets = tx.clone();
+		two_assets.inputs.pop();
+		two_assets.outputs.pop();
+		sign(&mut two_assets, &alice());
+		assert!(Utxo::validate_transaction(&two_assets).is_ok());
+
+		set_max_assets_per_transaction(3);
+		assert_ok!(Utxo::spend(Origin::signed(0), tx));
+	});
+}
+
+#[test]
+fn force_spend_ignores_time_locks_but_not_signatures() {
+	let locked = TransactionOutput { lock_height: 10, ..genesis_utxo() };
+	new_test_ext_with(GenesisConfig {
+		genesis_utxos: vec![locked.clone()],
+		total_genesis_supply: GENESIS_VALUE,
+		..Default::default()
+	}).execute_with(|| {
+		let mut tx = transfer(BlakeTwo256::hash_of(&locked), 50, key_of(&bob()));
+		sign(&mut tx, &bob());
+		assert_noop!(Utxo::force_spend(Origin::root(), tx.clone()), "signature must be valid");
+
+		sign(&mut tx, &alice());
+		assert_noop!(Utxo::spend(Origin::signed(0), tx.clone()), "input utxo is time-locked");
+		assert_noop!(Utxo::force_spend(Origin::signed(0), tx.clone()), BadOrigin);
+
+		assert_ok!(Utxo::force_spend(Origin::root(), tx.clone()));
+		assert!(!UtxoStore::contains_key(BlakeTwo256::hash_of(&locked)));
+		assert_eq!(UtxoStore::get(out_point(&tx, 0)).unwrap().value, 50);
+	});
+}
+
+#[test]
+fn addresses_round_trip_and_catch_typos() {
+	let key = key_of(&alice());
+	for prefix in [0u8, 42].iter() {
+		let address = format_address(key, *prefix).unwrap();
+		assert_eq!(parse_address(&address, *prefix), Ok(key));
+	}
+
+	let address = format_address(key, 42).unwrap();
+	assert_eq!(parse_address(&address, 0), Err("address is for network 42, expected 0".into()));
+
+	let mut corrupted = address.clone();
+	let last = corrupted.pop().unwrap();
+	corrupted.push(if last == '1' { '2' } else { '1' });
+	assert!(parse_address(&corrupted, 42).unwrap_err().starts_with("invalid address"));
+}
+
+#[test]
+fn addresses_reject_network_prefixes_from_64() {
+	let key = key_of(&alice());
+	assert!(format_address(key, 63).is_ok());
+	assert_eq!(format_address(key, 64), Err("network prefix must be below 64, got 64".into()));
+
+	let address = format_address(key, 42).unwrap();
+	assert_eq!(parse_address(&address, 200), Err("network prefix must be below 64, got 200".into()));
+}
+
+#[test]
+fn vesting_rewards_release_nothing_before_the_cliff() {
+	new_test_ext().execute_with(|| {
+		set_reward_vesting_period(100);
+		set_reward_vesting_tranches(4);
+		set_reward_cliff_blocks(60);
+		let authority = H256::repeat_byte(1);
+		RewardTotal::put(1000);
+		Utxo::disperse_reward(&[authority]);
+		assert_eq!(Utxo::rewards_in_block(1), vec![(authority, 1000)]);
+
+		let spendable = |now| -> Value {
+			Utxo::utxos_of(authority).iter()
+				.filter( |(out_point, utxo)| Utxo::is_spendable(out_point, utxo, now, true).is_ok() )
+				.map( |(_, utxo)| utxo.value )
+				.sum()
+		};
+		assert_eq!(Utxo::utxos_of(authority).len(), 4);
+		assert_eq!(spendable(60), 0);
+		// The two tranches due by the cliff unlock together, then the rest follow in turn.
+		assert_eq!(spendable(61), 500);
+		assert_eq!(spendable(76), 750);
+		assert_eq!(spendable(101), 1000);
+	});
+}
+
+#[test]
+fn integrity_scan_reports_bad_entries_across_calls() {
+	new_test_ext().execute_with(|| {
+		let bad = H256::repeat_byte(9);
+		UtxoStore::insert(bad, TransactionOutput { value: 0, ..genesis_utxo() });
+		assert_noop!(Utxo::scan_integrity(Origin::signed(0), 10), BadOrigin);
+
+		let problems = || System::events().into_iter()
+			.filter_map( |r| match r.event {
+				TestEvent::utxo(Event::IntegrityProblem(out_point, fault)) => Some((out_point, fault)),
+				_ => None,
+			})
+			.collect::<Vec<_>>();
+		let scanned = || System::events().into_iter()
+			.filter_map( |r| match r.event {
+				TestEvent::utxo(Event::IntegrityScanned(scanned, found)) => Some((scanned, found)),
+				_ => None,
+			})
+			.last();
+
+		// One entry per call, resuming each time, until the end is reached.
+		assert_ok!(Utxo::scan_integrity(Origin::root(), 1));
+		assert_eq!(scanned(), Some((1, problems().len() as u32)));
+		assert_ok!(Utxo::scan_integrity(Origin::root(), 1));
+		assert_eq!(problems(), vec![(bad, IntegrityFault::ZeroValue)]);
+		assert_ok!(Utxo::scan_integrity(Origin::root(), 1));
+		assert_eq!(scanned(), Some((0, 0)));
+
+		// Then it starts over.
+		assert_ok!(Utxo::scan_integrity(Origin::root(), 10));
+		assert_eq!(scanned(), Some((2, 1)));
+		assert_eq!(problems().len(), 2);
+	});
+}
+
+#[test]
+fn block_state_delta_reflects_net_changes() {
+	new_test_ext().execute_with(|| {
+		set_emit_state_delta(true);
+		let split = split_genesis(&[30, 70]);
+		let mut tx = transfer(out_point(&split, 0), 30, key_of(&bob()));
+		sign(&mut tx, &alice());
+		assert_ok!(Utxo::spend(Origin::signed(0), tx.clone()));
+		Utxo::deposit_state_delta();
+
+		// The split's first output was created and spent in the block, so it's left out.
+		let mut created = vec![
+			(out_point(&split, 1), split.outputs[1].clone()),
+			(out_point(&tx, 0), tx.outputs[0].clone()),
+		];
+		created.sort();
+		let delta = TestEvent::utxo(Event::BlockStateDelta(created, vec![genesis_out_point()]));
+		let deltas = || System::events().into_iter()
+			.filter( |r| match r.event { TestEvent::utxo(Event::BlockStateDelta(..)) => true, _ => false } )
+			.count();
+		assert_eq!(System::events().last().unwrap().event, delta);
+
+		// The changes are cleared, so a block without any emits nothing.
+		Utxo::deposit_state_delta();
+		assert_eq!(deltas(), 1);
+	});
+}